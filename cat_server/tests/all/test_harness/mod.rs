@@ -4,7 +4,7 @@ use crate::utilities::retry_loop;
 use self::mocks::{MockCatFactsApi, MockCatImagesApi};
 use actix_rt::System;
 use cat_server::{initialise_tracing, run_server, Configuration};
-use mock_jaeger_collector::DetachedJaegerCollectorServer;
+use mock_jaeger_collector::{CollectorSession, DetachedJaegerCollectorServer};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_tracing::TracingMiddleware;
 use std::future::pending;
@@ -91,11 +91,11 @@ pub struct TestHarness {
     /// The mock cat facts API.
     pub mock_cat_facts_api: MockCatFactsApi,
 
-    /// A reference to the shared jaeger trace collector.
-    /// It should be noted that this is static and shared across tests.
-    /// Tests should therefore only be querying for specific traces they
-    /// produce.
-    pub jaeger_collector_server: &'static DetachedJaegerCollectorServer,
+    /// This test's session on the shared jaeger trace collector. Tests should
+    /// register the IDs of the traces they produce with it, after which it
+    /// will only see those traces. Their spans are released from the collector
+    /// when the harness is dropped.
+    pub jaeger_session: CollectorSession,
 }
 
 impl TestHarness {
@@ -135,7 +135,7 @@ impl TestHarness {
             config,
             mock_cat_images_api,
             mock_cat_facts_api,
-            jaeger_collector_server: mock_otel_collector,
+            jaeger_session: mock_otel_collector.open_session(),
         }
     }
 
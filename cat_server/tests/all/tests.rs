@@ -1,11 +1,14 @@
 use crate::api_models::CatFactAndImageUrl;
 use crate::test_harness::TestHarness;
-use crate::utilities::retry_loop::{retry_until_ok, RetryTimeoutError};
 use crate::utilities::span_extensions::SpanExt;
-use anyhow::{anyhow, Context};
+use actix_rt::time::interval;
+use anyhow::Context;
+use cat_server::SERVER_NAME;
 use mock_jaeger_collector::{
-    jaeger_models::{Span, TagValue},
-    DetachedJaegerCollectorServer,
+    assertions::{named, TraceAssertions},
+    jaeger_models::SpanWithProcess,
+    reports::TraceReport,
+    CollectorSession, WaitForTraceError,
 };
 use opentelemetry::global::force_flush_tracer_provider;
 use prometheus_parse::{Scrape, Value};
@@ -178,30 +181,24 @@ pub async fn cat_endpoint_sends_a_trace_that_show_outgoing_http_calls() {
 
         test_span.otel_trace_id()
     };
+    test_harness.jaeger_session.register_trace_id(&trace_id);
 
     // Assert
     // We now expect, within a reasonable time frame, for our span to be available in our
     // local jaeger instance
-    wait_10_seconds_for_trace(test_harness.jaeger_collector_server, trace_id, |trace| {
-        let image_request_span = trace
-            .descendants()
-            .find(|s| s.borrow().operation_name == "GET /v1/images/search")
-            .ok_or_else(|| anyhow!(r#"No span found named "GET /v1/images/search""#))?;
-
-        check_tag(&image_request_span, "http.method", TagValue::String("GET"))
-            .context("cat images api span was not correct")?;
-        check_tag(&image_request_span, "http.status_code", TagValue::Long(200))
-            .context("cat images api span was not correct")?;
-
-        let fact_request_span = trace
-            .descendants()
-            .find(|s| s.borrow().operation_name == "GET /fact")
-            .ok_or_else(|| anyhow!(r#"No span found named "GET /fact""#))?;
-
-        check_tag(&fact_request_span, "http.method", TagValue::String("GET"))
-            .context("cat images api span was not correct")?;
-        check_tag(&fact_request_span, "http.status_code", TagValue::Long(200))
-            .context("cat images api span was not correct")
+    wait_for_trace(&test_harness.jaeger_session, trace_id, |trace| {
+        trace.expect_span(
+            named("GET /v1/images/search")
+                .with_tag("http.method", "GET")
+                .with_tag("http.status_code", 200),
+        )?;
+        trace.expect_span(
+            named("GET /fact")
+                .from_service(SERVER_NAME)
+                .with_tag("http.method", "GET")
+                .with_tag("http.status_code", 200),
+        )?;
+        Ok(())
     })
     .await
     .expect("Expected trace was not available within timeout");
@@ -237,48 +234,24 @@ pub async fn cat_endpoint_sends_a_trace_that_shows_the_incoming_http_request() {
 
         test_span.otel_trace_id()
     };
+    test_harness.jaeger_session.register_trace_id(&trace_id);
 
     // Assert
     // We now expect, within a reasonable time frame, for our span to be available in our
     // local jaeger instance
-    wait_10_seconds_for_trace(
-        test_harness.jaeger_collector_server,
-        trace_id.clone(),
-        |trace| {
-            let span_node = trace
-                .descendants()
-                .find(|s| s.borrow().operation_name == "HTTP request")
-                .ok_or_else(|| anyhow!(r#"No span found named "HTTP request""#))?;
-
-            check_tag(&span_node, "http.method", TagValue::String("GET"))
-                .context("span method was not correct")?;
-            check_tag(&span_node, "http.route", TagValue::String("/cat"))
-                .context("span route was not correct")?;
-            check_tag(&span_node, "http.status_code", TagValue::Long(200))
-                .context("span status code was not correct")
-        },
-    )
+    wait_for_trace(&test_harness.jaeger_session, trace_id, |trace| {
+        trace.expect_span(
+            named("HTTP request")
+                .with_tag("http.method", "GET")
+                .with_tag("http.route", "/cat")
+                .with_tag("http.status_code", 200),
+        )?;
+        Ok(())
+    })
     .await
     .expect("Expected trace was not available within timeout");
 }
 
-fn check_tag(span: &Node<Span>, key: &str, expected_value: TagValue) -> Result<(), anyhow::Error> {
-    let span_ref = span.borrow();
-    let tag = span_ref
-        .get_tag(key)
-        .ok_or_else(|| anyhow!(format!("No tag with key {} was found", key)))?;
-
-    let value = tag.value().context("Could not interpret tag value")?;
-    if value == expected_value {
-        Ok(())
-    } else {
-        Err(anyhow!(format!(
-            "Tag with key {} was found, but its value was {:?}, not {:?}",
-            key, value, expected_value
-        )))
-    }
-}
-
 async fn parse_metrics_response(response: Response) -> Result<Scrape, anyhow::Error> {
     let text = response.text().await.context("Failed to read body")?;
     let lines = text.lines().map(|line| Ok(line.to_owned()));
@@ -286,27 +259,38 @@ async fn parse_metrics_response(response: Response) -> Result<Scrape, anyhow::Er
     Ok(parsed_scrape)
 }
 
-async fn wait_10_seconds_for_trace<F>(
-    otel_collector: &DetachedJaegerCollectorServer,
+async fn wait_for_trace<F>(
+    jaeger_session: &CollectorSession,
     trace_id: String,
     check_trace: F,
-) -> Result<(), RetryTimeoutError<anyhow::Error>>
+) -> Result<Node<SpanWithProcess>, WaitForTraceError>
 where
-    F: Fn(Node<Span>) -> Result<(), anyhow::Error>,
+    F: Fn(&Node<SpanWithProcess>) -> Result<(), anyhow::Error>,
 {
-    let timeout = Duration::from_secs(5);
-    retry_until_ok(
-        || async {
-            // Since our telemetry state is global and shared between our
-            // test and our server, we can cheat a little here and force
-            // `opentelemetry` to flush any pending traces
+    // Since our telemetry state is global and shared between our
+    // test and our server, we can cheat a little here and force
+    // `opentelemetry` to flush any pending traces, rather than waiting
+    // for its batch exporter to send them. Spans may still be ended
+    // after a flush, so keep flushing until we stop waiting
+    let flush_periodically = actix_rt::spawn(async {
+        let mut flushes = interval(Duration::from_millis(100));
+        loop {
+            flushes.tick().await;
             force_flush_tracer_provider();
-            let trace = otel_collector.get_trace(&trace_id).await?;
-            check_trace(trace)
-        },
-        timeout,
-        timeout,
-        Duration::from_millis(100),
-    )
-    .await
+        }
+    });
+    let result = jaeger_session
+        .wait_for_trace(&trace_id, check_trace, Duration::from_secs(5))
+        .await;
+    flush_periodically.abort();
+
+    // Leave diagrams of the trace we last saw under `target/trace-reports`, to help
+    // work out why it was not as expected
+    if let Some(trace) = result.as_ref().err().and_then(|error| error.last_seen()) {
+        match TraceReport::new(&trace).write(&trace_id) {
+            Ok(paths) => eprintln!("Wrote trace reports to {:?}", paths),
+            Err(error) => eprintln!("Failed to write trace reports: {:#}", error),
+        }
+    }
+    result
 }
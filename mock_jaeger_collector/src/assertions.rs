@@ -0,0 +1,514 @@
+//! Fluent matchers for asserting on the spans of a trace.
+//!
+//! ```ignore
+//! use mock_jaeger_collector::assertions::{event, named, TraceAssertions};
+//!
+//! trace.expect_span(
+//!     named("GET /fact")
+//!         .with_tag("http.status_code", 200)
+//!         .child_of(named("get_cat_fact_and_image")),
+//! )?;
+//! trace.expect_span(
+//!     named("GET /fact").with_event(event("exception").with_field("exception.message", "timed out")),
+//! )?;
+//! ```
+//!
+//! When no span matches, the error renders the trace as a tree, noting why each span with
+//! the expected name did not match. Expected tag values may be any Rust string, integer,
+//! float or bool, but longs and doubles only match values of the same type, since
+//! exporters differ in which they send, unless [`SpanMatcher::with_loose_numbers`] is used.
+
+use std::fmt::{self, Display, Formatter};
+
+use rctree::Node;
+
+use crate::jaeger_models::{Log, SpanWithProcess, Tag, TagValue, TraceForest};
+use crate::rendering::RenderTree;
+
+/// A tag value expected by a [`SpanMatcher`], converted from the Rust value it is given.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExpectedTagValue {
+    String(String),
+    Double(f64),
+    Bool(bool),
+    Long(i64),
+}
+
+impl ExpectedTagValue {
+    pub(crate) fn matches(&self, actual: &TagValue) -> bool {
+        match (self, actual) {
+            (Self::String(expected), TagValue::String(actual)) => expected == actual,
+            (Self::Double(expected), TagValue::Double(actual)) => expected == actual,
+            (Self::Bool(expected), TagValue::Bool(actual)) => expected == actual,
+            (Self::Long(expected), TagValue::Long(actual)) => expected == actual,
+            _ => false,
+        }
+    }
+
+    /// Like [`ExpectedTagValue::matches`], but longs and doubles with the same numeric
+    /// value match one another.
+    pub(crate) fn matches_loosely(&self, actual: &TagValue) -> bool {
+        match (self.as_number(), actual.as_number()) {
+            (Some(expected), Some(actual)) => expected == actual,
+            _ => self.matches(actual),
+        }
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Double(value) => Some(*value),
+            Self::Long(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ExpectedTagValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(value) => write!(f, "{:?}", value),
+            Self::Double(value) => write!(f, "{:?}", value),
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::Long(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl From<&str> for ExpectedTagValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_owned())
+    }
+}
+
+impl From<String> for ExpectedTagValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<f64> for ExpectedTagValue {
+    fn from(value: f64) -> Self {
+        Self::Double(value)
+    }
+}
+
+impl From<bool> for ExpectedTagValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<f32> for ExpectedTagValue {
+    fn from(value: f32) -> Self {
+        Self::Double(value.into())
+    }
+}
+
+macro_rules! impl_from_integer {
+    ($($integer:ty),*) => {
+        $(
+            impl From<$integer> for ExpectedTagValue {
+                fn from(value: $integer) -> Self {
+                    Self::Long(value.into())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_integer!(i8, i16, i32, u8, u16, u32);
+
+/// Integers too wide to always fit in a long saturate at `i64::MAX`, rather than wrapping
+/// around to match negative tag values.
+macro_rules! impl_from_wide_integer {
+    ($($integer:ty),*) => {
+        $(
+            impl From<$integer> for ExpectedTagValue {
+                fn from(value: $integer) -> Self {
+                    Self::Long(i64::try_from(value).unwrap_or(i64::MAX))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_wide_integer!(u64, usize);
+
+impl From<isize> for ExpectedTagValue {
+    fn from(value: isize) -> Self {
+        // `isize` is no wider than 64 bits on any platform Rust supports
+        Self::Long(value as i64)
+    }
+}
+
+impl From<i64> for ExpectedTagValue {
+    fn from(value: i64) -> Self {
+        Self::Long(value)
+    }
+}
+
+/// Describes a span expected in a trace. Start with [`named`] or [`any_span`], then
+/// narrow the match with the builder methods.
+#[derive(Clone, Debug)]
+pub struct SpanMatcher {
+    operation_name: Option<String>,
+    service_name: Option<String>,
+    tags: Vec<(String, ExpectedTagValue)>,
+    process_tags: Vec<(String, ExpectedTagValue)>,
+    events: Vec<EventMatcher>,
+    parent: Option<Box<SpanMatcher>>,
+    loose_numbers: bool,
+}
+
+/// Match spans with the given operation name.
+pub fn named(operation_name: impl Into<String>) -> SpanMatcher {
+    SpanMatcher {
+        operation_name: Some(operation_name.into()),
+        ..any_span()
+    }
+}
+
+/// Match any span.
+pub fn any_span() -> SpanMatcher {
+    SpanMatcher {
+        operation_name: None,
+        service_name: None,
+        tags: Vec::new(),
+        process_tags: Vec::new(),
+        events: Vec::new(),
+        parent: None,
+        loose_numbers: false,
+    }
+}
+
+impl SpanMatcher {
+    /// Only match spans with a tag with the given key and value.
+    pub fn with_tag(mut self, key: impl Into<String>, value: impl Into<ExpectedTagValue>) -> Self {
+        self.tags.push((key.into(), value.into()));
+        self
+    }
+
+    /// Only match spans sent by the service with the given name.
+    pub fn from_service(mut self, service_name: impl Into<String>) -> Self {
+        self.service_name = Some(service_name.into());
+        self
+    }
+
+    /// Only match spans sent by a process with a tag with the given key and value.
+    pub fn with_process_tag(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<ExpectedTagValue>,
+    ) -> Self {
+        self.process_tags.push((key.into(), value.into()));
+        self
+    }
+
+    /// Only match spans with an event, or log, matching `event`.
+    pub fn with_event(mut self, event: EventMatcher) -> Self {
+        self.events.push(event);
+        self
+    }
+
+    /// Compare numeric tags by value, so that an expected `200` matches a tag sent as the
+    /// double `200.0`, and vice versa. By default, the types must match too.
+    pub fn with_loose_numbers(mut self) -> Self {
+        self.loose_numbers = true;
+        self
+    }
+
+    /// Only match spans whose parent matches `parent`.
+    pub fn child_of(mut self, parent: SpanMatcher) -> Self {
+        self.parent = Some(Box::new(parent));
+        self
+    }
+
+    /// Test whether the span matches.
+    pub fn matches(&self, span: &Node<SpanWithProcess>) -> bool {
+        self.mismatches(span).is_empty()
+    }
+
+    /// Describe each way in which the span does not match, if any.
+    pub fn mismatches(&self, span: &Node<SpanWithProcess>) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        {
+            let span = span.borrow();
+            if let Some(operation_name) = &self.operation_name {
+                if span.operation_name != *operation_name {
+                    mismatches.push(format!("operation name was {:?}", span.operation_name));
+                }
+            }
+            if let Some(service_name) = &self.service_name {
+                if span.service_name() != service_name {
+                    mismatches.push(format!("service was {:?}", span.service_name()));
+                }
+            }
+            for (key, expected) in &self.tags {
+                match span.get_tag(key) {
+                    None => mismatches.push(format!("had no {} tag", key)),
+                    Some(tag) => {
+                        check_tag_value(&mut mismatches, key, tag, expected, self.loose_numbers)
+                    }
+                }
+            }
+            for (key, expected) in &self.process_tags {
+                let description = format!("process tag {}", key);
+                match span.get_process_tag(key) {
+                    None => mismatches.push(format!("had no {}", description)),
+                    Some(tag) => check_tag_value(
+                        &mut mismatches,
+                        &description,
+                        tag,
+                        expected,
+                        self.loose_numbers,
+                    ),
+                }
+            }
+            for event_matcher in &self.events {
+                let candidates: Vec<&Log> = span
+                    .events()
+                    .iter()
+                    .filter(|log| event_matcher.is_candidate(log))
+                    .collect();
+                if candidates.is_empty() {
+                    mismatches.push(format!("had no {}", event_matcher));
+                } else if !candidates.iter().any(|log| event_matcher.matches(log)) {
+                    // Explain the mismatch of the last candidate, as the most recent event
+                    // is usually the one of interest.
+                    let reasons = event_matcher.mismatches(candidates[candidates.len() - 1]);
+                    mismatches.push(format!(
+                        "{} {}",
+                        event_matcher.describe_name(),
+                        reasons.join(", ")
+                    ));
+                }
+            }
+        }
+        if let Some(parent_matcher) = &self.parent {
+            match span.parent() {
+                None => mismatches.push("had no parent".into()),
+                Some(parent) => {
+                    let parent_mismatches = parent_matcher.mismatches(&parent);
+                    if !parent_mismatches.is_empty() {
+                        mismatches.push(format!("parent {}", parent_mismatches.join(", ")));
+                    }
+                }
+            }
+        }
+        mismatches
+    }
+}
+
+fn check_tag_value(
+    mismatches: &mut Vec<String>,
+    description: &str,
+    tag: &Tag,
+    expected: &ExpectedTagValue,
+    loose_numbers: bool,
+) {
+    match tag.value() {
+        Ok(actual) if loose_numbers && expected.matches_loosely(&actual) => (),
+        Ok(actual) if expected.matches(&actual) => (),
+        Ok(actual) if expected.matches_loosely(&actual) => mismatches.push(format!(
+            "{} was {:?}, not {:?}; to compare numbers of either type by value, use \
+             with_loose_numbers",
+            description, actual, expected
+        )),
+        Ok(actual) => mismatches.push(format!(
+            "{} was {:?}, not {}",
+            description, actual, expected
+        )),
+        Err(error) => mismatches.push(format!("{} was invalid: {}", description, error)),
+    }
+}
+
+impl Display for SpanMatcher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.operation_name {
+            Some(operation_name) => write!(f, "span named {:?}", operation_name)?,
+            None => write!(f, "any span")?,
+        }
+        if let Some(service_name) = &self.service_name {
+            write!(f, " from {:?}", service_name)?;
+        }
+        let process_tags = self
+            .process_tags
+            .iter()
+            .map(|(key, value)| (format!("process tag {}", key), value));
+        let tags = self.tags.iter().map(|(key, value)| (key.clone(), value));
+        for (index, (key, value)) in tags.chain(process_tags).enumerate() {
+            let separator = if index == 0 { " with" } else { " and" };
+            write!(f, "{} {} = {}", separator, key, value)?;
+        }
+        for event in &self.events {
+            write!(f, ", with {}", event)?;
+        }
+        if let Some(parent) = &self.parent {
+            write!(f, ", child of {}", parent)?;
+        }
+        Ok(())
+    }
+}
+
+/// Describes an event expected on a span. Start with [`event`] or [`any_event`], then
+/// narrow the match with [`EventMatcher::with_field`].
+#[derive(Clone, Debug)]
+pub struct EventMatcher {
+    name: Option<String>,
+    fields: Vec<(String, ExpectedTagValue)>,
+    loose_numbers: bool,
+}
+
+/// Match events with the given name, such as `exception`.
+pub fn event(name: impl Into<String>) -> EventMatcher {
+    EventMatcher {
+        name: Some(name.into()),
+        ..any_event()
+    }
+}
+
+/// Match any event.
+pub fn any_event() -> EventMatcher {
+    EventMatcher {
+        name: None,
+        fields: Vec::new(),
+        loose_numbers: false,
+    }
+}
+
+impl EventMatcher {
+    /// Only match events with a field with the given key and value.
+    pub fn with_field(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<ExpectedTagValue>,
+    ) -> Self {
+        self.fields.push((key.into(), value.into()));
+        self
+    }
+
+    /// Compare numeric fields by value. See [`SpanMatcher::with_loose_numbers`].
+    pub fn with_loose_numbers(mut self) -> Self {
+        self.loose_numbers = true;
+        self
+    }
+
+    /// Test whether the event matches.
+    pub fn matches(&self, log: &Log) -> bool {
+        self.mismatches(log).is_empty()
+    }
+
+    /// Describe each way in which the event does not match, if any.
+    pub fn mismatches(&self, log: &Log) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        if !self.is_candidate(log) {
+            mismatches.push(format!("name was {:?}", log.event_name()));
+        }
+        for (key, expected) in &self.fields {
+            match log.get_field(key) {
+                None => mismatches.push(format!("had no {} field", key)),
+                Some(field) => {
+                    check_tag_value(&mut mismatches, key, field, expected, self.loose_numbers)
+                }
+            }
+        }
+        mismatches
+    }
+
+    fn is_candidate(&self, log: &Log) -> bool {
+        self.name
+            .as_ref()
+            .is_none_or(|name| log.event_name() == Some(name.as_str()))
+    }
+
+    fn describe_name(&self) -> String {
+        match &self.name {
+            Some(name) => format!("event {:?}", name),
+            None => "event".into(),
+        }
+    }
+}
+
+impl Display for EventMatcher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.describe_name())?;
+        for (index, (key, value)) in self.fields.iter().enumerate() {
+            let separator = if index == 0 { " with" } else { " and" };
+            write!(f, "{} {} = {}", separator, key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// The error returned when no span in a trace matches a [`SpanMatcher`]. It describes
+/// the actual trace, noting why each span with the expected name did not match.
+#[derive(Debug)]
+pub struct SpanNotFoundError {
+    expected: String,
+    actual: String,
+}
+
+impl Display for SpanNotFoundError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "No span matched: {}\nActual trace:\n{}",
+            self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for SpanNotFoundError {}
+
+pub trait TraceAssertions {
+    /// Find the first span in the trace, in depth-first order, matching `matcher`.
+    fn expect_span(&self, matcher: SpanMatcher)
+        -> Result<Node<SpanWithProcess>, SpanNotFoundError>;
+}
+
+impl TraceAssertions for Node<SpanWithProcess> {
+    fn expect_span(
+        &self,
+        matcher: SpanMatcher,
+    ) -> Result<Node<SpanWithProcess>, SpanNotFoundError> {
+        find_span(std::slice::from_ref(self), matcher)
+    }
+}
+
+/// Partial traces are searched tree by tree, in the order of [`TraceForest::roots`].
+impl TraceAssertions for TraceForest {
+    fn expect_span(
+        &self,
+        matcher: SpanMatcher,
+    ) -> Result<Node<SpanWithProcess>, SpanNotFoundError> {
+        find_span(self.roots(), matcher)
+    }
+}
+
+fn find_span(
+    roots: &[Node<SpanWithProcess>],
+    matcher: SpanMatcher,
+) -> Result<Node<SpanWithProcess>, SpanNotFoundError> {
+    let spans = || roots.iter().flat_map(|root| root.descendants());
+    if let Some(span) = spans().find(|span| matcher.matches(span)) {
+        return Ok(span);
+    }
+
+    let annotate = |span: &Node<SpanWithProcess>| {
+        let is_candidate = matcher
+            .operation_name
+            .as_ref()
+            .is_none_or(|name| *name == span.borrow().operation_name);
+        is_candidate.then(|| matcher.mismatches(span).join(", "))
+    };
+    let actual = roots
+        .iter()
+        .map(|root| root.render_tree().with_annotations(&annotate).to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Err(SpanNotFoundError {
+        expected: matcher.to_string(),
+        actual,
+    })
+}
@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+
+/// Where a [`DetachedJaegerCollectorServer`] listens, and where it persists the batches it
+/// receives. A port of `0` asks the operating system to allocate an available one.
+///
+/// [`DetachedJaegerCollectorServer`]: crate::DetachedJaegerCollectorServer
+#[derive(Clone)]
+pub struct Configuration {
+    pub host: String,
+    pub http_port: u16,
+    pub udp_port: u16,
+    pub otlp_port: u16,
+
+    /// A file to append every received batch to, which
+    /// [`DetachedJaegerCollectorServer::load`] can later read back.
+    ///
+    /// [`DetachedJaegerCollectorServer::load`]: crate::DetachedJaegerCollectorServer::load
+    pub persist_path: Option<PathBuf>,
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".into(),
+            http_port: 0,
+            udp_port: 0,
+            otlp_port: 0,
+            persist_path: None,
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::jaeger_models::{Log, Span, Tag, TagValue};
+
+/// The log field holding the name of an event, as OpenTelemetry and Zipkin exporters
+/// record it.
+pub const EVENT_NAME_FIELD: &str = "event";
+
+impl Log {
+    /// The time at which the event was logged.
+    pub fn time(&self) -> SystemTime {
+        let micros = Duration::from_micros(self.timestamp.unsigned_abs());
+        if self.timestamp >= 0 {
+            UNIX_EPOCH + micros
+        } else {
+            UNIX_EPOCH - micros
+        }
+    }
+
+    pub fn get_field(&self, key: &str) -> Option<&Tag> {
+        self.fields.iter().find(|f| f.key == key)
+    }
+
+    /// The event's name, held in its [`EVENT_NAME_FIELD`] field.
+    pub fn event_name(&self) -> Option<&str> {
+        match self.get_field(EVENT_NAME_FIELD)?.value() {
+            Ok(TagValue::String(name)) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+impl Span {
+    /// The span's logs, which OpenTelemetry calls events.
+    pub fn events(&self) -> &[Log] {
+        self.logs.as_deref().unwrap_or_default()
+    }
+
+    /// Find the first event with the given name.
+    pub fn find_event(&self, name: &str) -> Option<&Log> {
+        self.events()
+            .iter()
+            .find(|log| log.event_name() == Some(name))
+    }
+}
@@ -1,5 +1,7 @@
+mod log;
 mod span;
 mod tag;
 
+pub use log::*;
 pub use span::*;
 pub use tag::*;
@@ -1,4 +1,4 @@
-use crate::jaeger_models::{Span, Tag};
+use crate::jaeger_models::{Span, SpanRefType, Tag, TagValue};
 
 impl Span {
     pub fn get_tag(&self, key: &str) -> Option<&Tag> {
@@ -6,7 +6,71 @@ impl Span {
         tag
     }
 
+    /// The value of the tag with the given key, if it is present and valid.
+    pub fn tag_value(&self, key: &str) -> Option<TagValue<'_>> {
+        self.get_tag(key)?.value().ok()
+    }
+
+    /// The value of the string tag with the given key, such as `http.method`.
+    pub fn tag_str(&self, key: &str) -> Option<&str> {
+        self.tag_value(key)?.as_str()
+    }
+
+    /// The value of the long tag with the given key, such as `http.status_code`.
+    pub fn tag_i64(&self, key: &str) -> Option<i64> {
+        self.tag_value(key)?.as_i64()
+    }
+
+    /// The value of the double tag with the given key, such as `sampler.param`.
+    pub fn tag_f64(&self, key: &str) -> Option<f64> {
+        self.tag_value(key)?.as_f64()
+    }
+
+    /// The value of the bool tag with the given key, such as `error`.
+    pub fn tag_bool(&self, key: &str) -> Option<bool> {
+        self.tag_value(key)?.as_bool()
+    }
+
+    /// The value of the binary tag with the given key, such as `http.request.body`.
+    pub fn tag_bytes(&self, key: &str) -> Option<&[u8]> {
+        self.tag_value(key)?.as_bytes()
+    }
+
     pub fn hex_trace_id(&self) -> String {
         format!("{:016x}{:016x}", &self.trace_id_high, &self.trace_id_low)
     }
+
+    /// The span's references to other spans in its own trace, as pairs of the reference
+    /// type and the referenced span's ID. A `parent_span_id` is given as a `CHILD_OF`
+    /// reference, unless the span also lists it among its references.
+    pub fn references_in_trace(&self) -> Vec<(SpanRefType, i64)> {
+        let mut references = Vec::new();
+        if self.parent_span_id != 0 {
+            references.push((SpanRefType::CHILD_OF, self.parent_span_id));
+        }
+        for reference in self.references.iter().flatten() {
+            let is_in_trace = reference.trace_id_high == self.trace_id_high
+                && reference.trace_id_low == self.trace_id_low;
+            let reference = (reference.ref_type, reference.span_id);
+            if is_in_trace && !references.contains(&reference) {
+                references.push(reference);
+            }
+        }
+        references
+    }
+
+    /// The ID of the span this one is shown beneath in a span tree, as the Jaeger UI
+    /// chooses it: its `parent_span_id`, or else its first `CHILD_OF` reference, or else
+    /// its first `FOLLOWS_FROM` reference, within its own trace.
+    pub fn tree_parent_span_id(&self) -> Option<i64> {
+        let references = self.references_in_trace();
+        [SpanRefType::CHILD_OF, SpanRefType::FOLLOWS_FROM]
+            .iter()
+            .find_map(|ref_type| {
+                references
+                    .iter()
+                    .find(|(reference_type, _)| reference_type == ref_type)
+            })
+            .map(|(_, span_id)| *span_id)
+    }
 }
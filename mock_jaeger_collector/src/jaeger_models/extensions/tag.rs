@@ -1,7 +1,8 @@
 use crate::jaeger_models::{Tag, TagType};
-use anyhow::{anyhow, bail};
+use anyhow::{anyhow, bail, Context};
+use serde::de::DeserializeOwned;
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum TagValue<'t> {
     String(&'t str),
     Double(f64),
@@ -11,6 +12,19 @@ pub enum TagValue<'t> {
 }
 
 impl Tag {
+    /// Create a tag with a string value.
+    pub fn string(key: impl Into<String>, value: impl Into<String>) -> Self {
+        Self::new(
+            key.into(),
+            TagType::STRING,
+            value.into(),
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
     pub fn value(&self) -> Result<TagValue, anyhow::Error> {
         Ok(match self.v_type {
             TagType::BINARY => TagValue::Binary(
@@ -40,3 +54,66 @@ impl Tag {
         })
     }
 }
+
+impl<'t> TagValue<'t> {
+    pub fn as_str(self) -> Option<&'t str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(self) -> Option<f64> {
+        match self {
+            Self::Double(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(self) -> Option<bool> {
+        match self {
+            Self::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(self) -> Option<i64> {
+        match self {
+            Self::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(self) -> Option<&'t [u8]> {
+        match self {
+            Self::Binary(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value as a number, whether it was sent as a long or a double.
+    pub fn as_number(self) -> Option<f64> {
+        match self {
+            Self::Double(value) => Some(value),
+            Self::Long(value) => Some(value as f64),
+            _ => None,
+        }
+    }
+
+    /// Decode a binary value as UTF-8 text. String values are returned as they are.
+    pub fn decode_utf8(self) -> Result<&'t str, anyhow::Error> {
+        match self {
+            Self::String(value) => Ok(value),
+            Self::Binary(value) => {
+                std::str::from_utf8(value).context("Binary tag value was not valid UTF-8")
+            }
+            other => bail!("Expected a string or binary tag value, found {:?}", other),
+        }
+    }
+
+    /// Deserialize a binary or string value holding a JSON document.
+    pub fn decode_json<T: DeserializeOwned>(self) -> Result<T, anyhow::Error> {
+        let json = self.decode_utf8()?;
+        serde_json::from_str(json).context("Tag value was not the expected JSON")
+    }
+}
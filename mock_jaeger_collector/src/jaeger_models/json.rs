@@ -0,0 +1,457 @@
+//! Mirrors of the types in Jaeger's `model/json` package, which its query API, and so
+//! the Jaeger UI, uses to represent traces. They serialize to, and deserialize from, the
+//! same JSON as Jaeger, so traces can be written to test artifacts, compared with JSON
+//! fixtures, or loaded from the "Download JSON" button of a real Jaeger UI.
+//!
+//! ```ignore
+//! use mock_jaeger_collector::jaeger_models::json;
+//!
+//! let json = serde_json::to_string_pretty(&json::Trace::from_tree(&trace))?;
+//! let batches = json::parse_traces(&fixture)?[0].to_batches()?;
+//! ```
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Context};
+use rctree::Node;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thrift::OrderedFloat;
+
+use super::{Batch, SpanRef, SpanRefType, SpanWithProcess, Tag, TagType};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Trace {
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    pub spans: Vec<Span>,
+    #[serde(default)]
+    pub processes: BTreeMap<String, Process>,
+    #[serde(default)]
+    pub warnings: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Span {
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    #[serde(rename = "spanID")]
+    pub span_id: String,
+    #[serde(default)]
+    pub flags: i32,
+    pub operation_name: String,
+    #[serde(default)]
+    pub references: Vec<Reference>,
+    pub start_time: i64,
+    pub duration: i64,
+    #[serde(default)]
+    pub tags: Vec<KeyValue>,
+    #[serde(default)]
+    pub logs: Vec<Log>,
+    /// The key of the span's process in [`Trace::processes`].
+    #[serde(rename = "processID", default)]
+    pub process_id: String,
+    /// The span's process, which Jaeger embeds in the span in place of a `processID`
+    /// when the span is not part of a [`Trace`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process: Option<Process>,
+    #[serde(default)]
+    pub warnings: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reference {
+    pub ref_type: ReferenceType,
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    #[serde(rename = "spanID")]
+    pub span_id: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReferenceType {
+    ChildOf,
+    FollowsFrom,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Process {
+    pub service_name: String,
+    #[serde(default)]
+    pub tags: Vec<KeyValue>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Log {
+    pub timestamp: i64,
+    #[serde(default)]
+    pub fields: Vec<KeyValue>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyValue {
+    pub key: String,
+    #[serde(rename = "type")]
+    pub value_type: ValueType,
+    pub value: serde_json::Value,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    String,
+    Bool,
+    Int64,
+    Float64,
+    Binary,
+}
+
+impl Trace {
+    /// Build a trace from its spans, each paired with the process that emitted it.
+    /// Processes are deduplicated, and identified as `p1`, `p2`, etc., as Jaeger does.
+    pub fn from_spans(spans: Vec<(&super::Process, &super::Span)>) -> Self {
+        let trace_id = spans
+            .first()
+            .map(|(_, span)| trace_id_hex(span.trace_id_high, span.trace_id_low))
+            .unwrap_or_default();
+
+        let mut processes: Vec<Process> = Vec::new();
+        let spans = spans
+            .into_iter()
+            .map(|(process, span)| {
+                let process = Process::from(process);
+                let process_index = match processes.iter().position(|p| *p == process) {
+                    Some(index) => index,
+                    None => {
+                        processes.push(process);
+                        processes.len() - 1
+                    }
+                };
+                let mut span = Span::from(span);
+                span.process_id = format!("p{}", process_index + 1);
+                span
+            })
+            .collect();
+
+        Self {
+            trace_id,
+            spans,
+            processes: processes
+                .into_iter()
+                .enumerate()
+                .map(|(index, process)| (format!("p{}", index + 1), process))
+                .collect(),
+            warnings: None,
+        }
+    }
+
+    /// Build a trace from the spans of an assembled trace, in depth-first order.
+    pub fn from_tree(trace: &Node<SpanWithProcess>) -> Self {
+        let spans: Vec<SpanWithProcess> = trace.descendants().map(|s| s.borrow().clone()).collect();
+        Self::from_spans(
+            spans
+                .iter()
+                .map(|span| (&*span.process, &span.span))
+                .collect(),
+        )
+    }
+
+    /// Convert the trace back into the Jaeger Thrift model, with a batch for each
+    /// process, in the order the processes' first spans appear.
+    pub fn to_batches(&self) -> Result<Vec<Batch>, anyhow::Error> {
+        let mut batches: Vec<(Process, Vec<super::Span>)> = Vec::new();
+        for span in &self.spans {
+            let process = match &span.process {
+                Some(process) => process,
+                None => self.processes.get(&span.process_id).ok_or_else(|| {
+                    anyhow!(
+                        "Span {} refers to unknown process {:?}",
+                        span.span_id,
+                        span.process_id
+                    )
+                })?,
+            };
+            let thrift_span = super::Span::try_from(span)
+                .with_context(|| format!("Failed to convert span {}", span.span_id))?;
+            match batches.iter_mut().find(|(p, _)| p == process) {
+                Some((_, spans)) => spans.push(thrift_span),
+                None => batches.push((process.clone(), vec![thrift_span])),
+            }
+        }
+        batches
+            .into_iter()
+            .map(|(process, spans)| Ok(Batch::new((&process).try_into()?, spans, None, None)))
+            .collect()
+    }
+}
+
+/// Parse traces in any of the forms Jaeger writes them: a single trace, an array of
+/// traces, or a query API response, such as the Jaeger UI downloads, holding them under
+/// `data`.
+pub fn parse_traces(json: &str) -> Result<Vec<Trace>, anyhow::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Traces {
+        Response { data: Vec<Trace> },
+        Many(Vec<Trace>),
+        One(Trace),
+    }
+
+    let traces = serde_json::from_str(json).context("Failed to parse Jaeger JSON traces")?;
+    Ok(match traces {
+        Traces::Response { data } => data,
+        Traces::Many(traces) => traces,
+        Traces::One(trace) => vec![trace],
+    })
+}
+
+impl From<&super::Span> for Span {
+    fn from(span: &super::Span) -> Self {
+        let trace_id = trace_id_hex(span.trace_id_high, span.trace_id_low);
+
+        // Jaeger's domain model represents a span's parent as a `CHILD_OF` reference,
+        // rather than as a separate field.
+        let mut references = Vec::new();
+        if span.parent_span_id != 0 {
+            references.push(Reference {
+                ref_type: ReferenceType::ChildOf,
+                trace_id: trace_id.clone(),
+                span_id: span_id_hex(span.parent_span_id),
+            });
+        }
+        for reference in span.references.iter().flatten() {
+            let ref_type = if reference.ref_type == SpanRefType::FOLLOWS_FROM {
+                ReferenceType::FollowsFrom
+            } else {
+                ReferenceType::ChildOf
+            };
+            let reference = Reference {
+                ref_type,
+                trace_id: trace_id_hex(reference.trace_id_high, reference.trace_id_low),
+                span_id: span_id_hex(reference.span_id),
+            };
+            let is_duplicate = references.iter().any(|r: &Reference| {
+                r.ref_type == reference.ref_type
+                    && r.trace_id == reference.trace_id
+                    && r.span_id == reference.span_id
+            });
+            if !is_duplicate {
+                references.push(reference);
+            }
+        }
+
+        Self {
+            trace_id,
+            span_id: span_id_hex(span.span_id),
+            flags: span.flags,
+            operation_name: span.operation_name.clone(),
+            references,
+            start_time: span.start_time,
+            duration: span.duration,
+            tags: key_values(span.tags.as_deref()),
+            logs: span
+                .logs
+                .iter()
+                .flatten()
+                .map(|log| Log {
+                    timestamp: log.timestamp,
+                    fields: key_values(Some(&log.fields)),
+                })
+                .collect(),
+            process_id: String::new(),
+            process: None,
+            warnings: None,
+        }
+    }
+}
+
+impl From<&super::Process> for Process {
+    fn from(process: &super::Process) -> Self {
+        Self {
+            service_name: process.service_name.clone(),
+            tags: key_values(process.tags.as_deref()),
+        }
+    }
+}
+
+impl TryFrom<&Span> for super::Span {
+    type Error = anyhow::Error;
+
+    /// The span's first `CHILD_OF` reference within its own trace becomes its
+    /// `parent_span_id`, and any others are kept as references.
+    fn try_from(span: &Span) -> Result<Self, Self::Error> {
+        let (trace_id_high, trace_id_low) = parse_trace_id(&span.trace_id)?;
+        let parent_index = span.references.iter().position(|reference| {
+            reference.ref_type == ReferenceType::ChildOf && reference.trace_id == span.trace_id
+        });
+        let parent_span_id = match parent_index {
+            Some(index) => parse_span_id(&span.references[index].span_id)?,
+            None => 0,
+        };
+        let references = span
+            .references
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| Some(*index) != parent_index)
+            .map(|(_, reference)| {
+                let (trace_id_high, trace_id_low) = parse_trace_id(&reference.trace_id)?;
+                let ref_type = match reference.ref_type {
+                    ReferenceType::ChildOf => SpanRefType::CHILD_OF,
+                    ReferenceType::FollowsFrom => SpanRefType::FOLLOWS_FROM,
+                };
+                Ok(SpanRef::new(
+                    ref_type,
+                    trace_id_low,
+                    trace_id_high,
+                    parse_span_id(&reference.span_id)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        let logs = span
+            .logs
+            .iter()
+            .map(|log| Ok(super::Log::new(log.timestamp, tags(&log.fields)?)))
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+        Ok(super::Span::new(
+            trace_id_low,
+            trace_id_high,
+            parse_span_id(&span.span_id)?,
+            parent_span_id,
+            span.operation_name.clone(),
+            non_empty(references),
+            span.flags,
+            span.start_time,
+            span.duration,
+            non_empty(tags(&span.tags)?),
+            non_empty(logs),
+        ))
+    }
+}
+
+impl TryFrom<&Process> for super::Process {
+    type Error = anyhow::Error;
+
+    fn try_from(process: &Process) -> Result<Self, Self::Error> {
+        Ok(super::Process::new(
+            process.service_name.clone(),
+            non_empty(tags(&process.tags)?),
+        ))
+    }
+}
+
+impl TryFrom<&KeyValue> for Tag {
+    type Error = anyhow::Error;
+
+    fn try_from(key_value: &KeyValue) -> Result<Self, Self::Error> {
+        let key = key_value.key.clone();
+        let value = &key_value.value;
+        let invalid = || {
+            anyhow!(
+                "Tag {} has a value of the wrong type for {:?}: {}",
+                key_value.key,
+                key_value.value_type,
+                value
+            )
+        };
+        Ok(match key_value.value_type {
+            ValueType::String => {
+                let value = value.as_str().ok_or_else(invalid)?.to_owned();
+                Tag::new(key, TagType::STRING, value, None, None, None, None)
+            }
+            ValueType::Bool => {
+                let value = value.as_bool().ok_or_else(invalid)?;
+                Tag::new(key, TagType::BOOL, None, None, value, None, None)
+            }
+            ValueType::Int64 => {
+                let value = value.as_i64().ok_or_else(invalid)?;
+                Tag::new(key, TagType::LONG, None, None, None, value, None)
+            }
+            ValueType::Float64 => {
+                let value = OrderedFloat(value.as_f64().ok_or_else(invalid)?);
+                Tag::new(key, TagType::DOUBLE, None, value, None, None, None)
+            }
+            ValueType::Binary => {
+                let value = base64::decode(value.as_str().ok_or_else(invalid)?)
+                    .with_context(|| format!("Tag {} is not valid base64", key_value.key))?;
+                Tag::new(key, TagType::BINARY, None, None, None, None, value)
+            }
+        })
+    }
+}
+
+fn tags(key_values: &[KeyValue]) -> Result<Vec<Tag>, anyhow::Error> {
+    key_values.iter().map(Tag::try_from).collect()
+}
+
+/// The Thrift model's lists are optional; leave them out when they would be empty.
+fn non_empty<T>(items: Vec<T>) -> Option<Vec<T>> {
+    (!items.is_empty()).then_some(items)
+}
+
+fn key_values(tags: Option<&[super::Tag]>) -> Vec<KeyValue> {
+    tags.into_iter()
+        .flatten()
+        .filter_map(|tag| {
+            let (value_type, value) = match tag.v_type {
+                TagType::STRING => (ValueType::String, json!(tag.v_str.as_ref()?)),
+                TagType::BOOL => (ValueType::Bool, json!(tag.v_bool?)),
+                TagType::LONG => (ValueType::Int64, json!(tag.v_long?)),
+                TagType::DOUBLE => (ValueType::Float64, json!(tag.v_double?.0)),
+                TagType::BINARY => (
+                    ValueType::Binary,
+                    json!(base64::encode(tag.v_binary.as_ref()?)),
+                ),
+                _ => return None,
+            };
+            Some(KeyValue {
+                key: tag.key.clone(),
+                value_type,
+                value,
+            })
+        })
+        .collect()
+}
+
+/// Format a trace ID as Jaeger does, omitting the high half when it is unused.
+fn trace_id_hex(trace_id_high: i64, trace_id_low: i64) -> String {
+    if trace_id_high == 0 {
+        format!("{:016x}", trace_id_low)
+    } else {
+        format!("{:016x}{:016x}", trace_id_high, trace_id_low)
+    }
+}
+
+fn span_id_hex(span_id: i64) -> String {
+    format!("{:016x}", span_id)
+}
+
+/// Parse a trace ID of up to 32 hex digits into its high and low halves.
+fn parse_trace_id(trace_id: &str) -> Result<(i64, i64), anyhow::Error> {
+    if trace_id.is_empty() || trace_id.len() > 32 || !is_hex(trace_id) {
+        bail!("Invalid trace ID: {:?}", trace_id);
+    }
+    let value = u128::from_str_radix(trace_id, 16)
+        .with_context(|| format!("Invalid trace ID: {:?}", trace_id))?;
+    Ok(((value >> 64) as i64, value as i64))
+}
+
+fn parse_span_id(span_id: &str) -> Result<i64, anyhow::Error> {
+    if span_id.len() > 16 || !is_hex(span_id) {
+        bail!("Invalid span ID: {:?}", span_id);
+    }
+    let value = u64::from_str_radix(span_id, 16)
+        .with_context(|| format!("Invalid span ID: {:?}", span_id))?;
+    Ok(value as i64)
+}
+
+/// Whether `id` is made only of hex digits, as `from_str_radix` would also accept a
+/// leading `+`, which Jaeger does not.
+fn is_hex(id: &str) -> bool {
+    id.bytes().all(|b| b.is_ascii_hexdigit())
+}
@@ -1,6 +1,12 @@
 mod extensions;
 mod generated;
+pub mod json;
 pub(crate) mod span_tree;
+mod span_with_process;
+mod trace_graph;
 
 pub use extensions::*;
 pub use generated::*;
+pub use span_tree::TraceForest;
+pub use span_with_process::SpanWithProcess;
+pub use trace_graph::{SpanReference, TraceGraph};
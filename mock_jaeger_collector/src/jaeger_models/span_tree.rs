@@ -1,34 +1,148 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use anyhow::{anyhow, bail};
+use anyhow::bail;
 use itertools::Itertools as _;
 use nonempty::NonEmpty;
 use rctree::Node;
 
-use super::Span;
+use super::SpanWithProcess;
 
-pub fn build_span_tree(spans: impl IntoIterator<Item = Span>) -> Result<Node<Span>, anyhow::Error> {
-    let mut spans_grouped_by_parent: HashMap<i64, NonEmpty<Span>> = spans
-        .into_iter()
-        .sorted_by_key(|s| s.parent_span_id)
-        .group_by(|s| s.parent_span_id)
-        .into_iter()
-        .map(|(key, group)| (key, NonEmpty::from_vec(group.collect()).unwrap()))
-        .collect();
+/// Assemble spans into a tree, placing each beneath the span given by
+/// [`Span::tree_parent_span_id`]. This fails unless the spans form a single, complete
+/// tree; see [`TraceForest`] to assemble partial traces.
+pub fn build_span_tree(
+    spans: impl IntoIterator<Item = SpanWithProcess>,
+) -> Result<Node<SpanWithProcess>, anyhow::Error> {
+    TraceForest::new(spans).into_tree()
+}
+
+/// The spans of a trace, assembled into as many trees as they form.
+///
+/// A trace spanning several services may be missing spans which were sent elsewhere, such
+/// as the root span of an upstream caller. Rather than failing, as [`build_span_tree`]
+/// does, the spans beneath each missing span are kept as a separate tree, and the IDs of
+/// the missing spans are recorded.
+#[derive(Clone, Debug)]
+pub struct TraceForest {
+    roots: Vec<Node<SpanWithProcess>>,
+    missing_parent_span_ids: Vec<i64>,
+}
+
+impl TraceForest {
+    pub fn new(spans: impl IntoIterator<Item = SpanWithProcess>) -> Self {
+        let mut spans_grouped_by_parent: HashMap<i64, NonEmpty<SpanWithProcess>> = spans
+            .into_iter()
+            .map(|s| (s.tree_parent_span_id().unwrap_or(0), s))
+            .sorted_by_key(|(parent_span_id, _)| *parent_span_id)
+            .group_by(|(parent_span_id, _)| *parent_span_id)
+            .into_iter()
+            .map(|(key, group)| {
+                let spans = group.map(|(_, s)| s).collect();
+                (key, NonEmpty::from_vec(spans).unwrap())
+            })
+            .collect();
+        let span_ids: HashSet<i64> = spans_grouped_by_parent
+            .values()
+            .flat_map(|spans| spans.iter().map(|s| s.span_id))
+            .collect();
 
-    if spans_grouped_by_parent.is_empty() {
-        bail!("Traces must include at least one span.");
+        let missing_parent_span_ids: Vec<i64> = spans_grouped_by_parent
+            .keys()
+            .filter(|parent_span_id| **parent_span_id != 0 && !span_ids.contains(parent_span_id))
+            .copied()
+            .sorted()
+            .collect();
+        let mut root_spans: Vec<SpanWithProcess> = [0]
+            .iter()
+            .chain(&missing_parent_span_ids)
+            .filter_map(|parent_span_id| spans_grouped_by_parent.remove(parent_span_id))
+            .flatten()
+            .collect();
+        root_spans.sort_by_key(|s| s.start_time);
+
+        let mut roots = Vec::new();
+        for root_span in root_spans {
+            roots.push(attach_children(root_span, &mut spans_grouped_by_parent));
+        }
+        // Whatever is left refers to itself through its ancestors, so cannot be reached
+        // from any root. Break each cycle by making one of its spans a root.
+        while let Some(parent_span_id) = spans_grouped_by_parent.keys().next().copied() {
+            let NonEmpty { head, tail } = spans_grouped_by_parent.remove(&parent_span_id).unwrap();
+            if let Some(others) = NonEmpty::from_vec(tail) {
+                spans_grouped_by_parent.insert(parent_span_id, others);
+            }
+            roots.push(attach_children(head, &mut spans_grouped_by_parent));
+        }
+
+        Self {
+            roots,
+            missing_parent_span_ids,
+        }
     }
 
-    let spans_without_parent = spans_grouped_by_parent
-        .remove(&0_i64)
-        .ok_or_else(|| anyhow!("No root span found"))?;
+    /// The trees of the trace, ordered by the start times of their roots.
+    pub fn roots(&self) -> &[Node<SpanWithProcess>] {
+        &self.roots
+    }
+
+    /// The trees whose root span refers to a parent which was not received.
+    pub fn orphans(&self) -> impl Iterator<Item = &Node<SpanWithProcess>> {
+        self.roots
+            .iter()
+            .filter(|root| match root.borrow().tree_parent_span_id() {
+                Some(parent_span_id) => self.missing_parent_span_ids.contains(&parent_span_id),
+                None => false,
+            })
+    }
 
-    if spans_without_parent.len() > 1 {
-        bail!("Multiple root spans found");
+    /// The IDs of spans which other spans refer to as their parents, but which were not
+    /// received.
+    pub fn missing_parent_span_ids(&self) -> &[i64] {
+        &self.missing_parent_span_ids
     }
 
-    let tree = Node::new(spans_without_parent.first().to_owned());
+    /// Whether the spans form a single tree, with no missing parents.
+    pub fn is_complete(&self) -> bool {
+        match self.roots.as_slice() {
+            [root] => root.borrow().tree_parent_span_id().is_none(),
+            _ => false,
+        }
+    }
+
+    /// Get the single tree formed by the spans, failing if they do not form one.
+    pub fn into_tree(self) -> Result<Node<SpanWithProcess>, anyhow::Error> {
+        if self.roots.is_empty() {
+            bail!("Traces must include at least one span.");
+        }
+        if !self.missing_parent_span_ids.is_empty() {
+            bail!(
+                "Spans found with missing parents: {}",
+                self.missing_parent_span_ids
+                    .iter()
+                    .map(|span_id| format!("{:016x}", span_id))
+                    .join(", ")
+            );
+        }
+        if self
+            .roots
+            .iter()
+            .all(|root| root.borrow().tree_parent_span_id().is_some())
+        {
+            bail!("No root span found");
+        }
+        if self.roots.len() > 1 {
+            bail!("Multiple root spans found");
+        }
+        Ok(self.roots.into_iter().next().unwrap())
+    }
+}
+
+/// Build the tree beneath `span`, taking its descendants from `spans_grouped_by_parent`.
+fn attach_children(
+    span: SpanWithProcess,
+    spans_grouped_by_parent: &mut HashMap<i64, NonEmpty<SpanWithProcess>>,
+) -> Node<SpanWithProcess> {
+    let tree = Node::new(span);
     let mut leaf_nodes_to_populate = vec![tree.clone()];
 
     while !leaf_nodes_to_populate.is_empty() {
@@ -47,9 +161,5 @@ pub fn build_span_tree(spans: impl IntoIterator<Item = Span>) -> Result<Node<Spa
         }
     }
 
-    if !spans_grouped_by_parent.is_empty() {
-        anyhow::bail!("Spans found with missing parents");
-    }
-
-    Ok(tree)
+    tree
 }
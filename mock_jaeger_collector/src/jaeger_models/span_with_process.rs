@@ -0,0 +1,42 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use super::{Process, Span, Tag};
+
+/// A span, together with the process which sent it. It dereferences to the [`Span`], so
+/// the span's fields and methods can be used directly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpanWithProcess {
+    pub span: Span,
+    pub process: Arc<Process>,
+}
+
+impl SpanWithProcess {
+    pub fn new(span: Span, process: Arc<Process>) -> Self {
+        Self { span, process }
+    }
+
+    /// The name of the service which sent the span.
+    pub fn service_name(&self) -> &str {
+        &self.process.service_name
+    }
+
+    /// Get a tag of the process which sent the span, such as `hostname` or `client-uuid`.
+    pub fn get_process_tag(&self, key: &str) -> Option<&Tag> {
+        self.process.tags.as_ref()?.iter().find(|t| t.key == key)
+    }
+}
+
+impl Deref for SpanWithProcess {
+    type Target = Span;
+
+    fn deref(&self) -> &Span {
+        &self.span
+    }
+}
+
+impl DerefMut for SpanWithProcess {
+    fn deref_mut(&mut self) -> &mut Span {
+        &mut self.span
+    }
+}
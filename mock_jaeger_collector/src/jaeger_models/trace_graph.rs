@@ -0,0 +1,88 @@
+use rctree::Node;
+
+use super::span_tree::{build_span_tree, TraceForest};
+use super::{SpanRefType, SpanWithProcess};
+
+/// A reference from one span to another in the same trace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpanReference {
+    pub ref_type: SpanRefType,
+    /// The span holding the reference, such as a child span.
+    pub from_span_id: i64,
+    /// The span referred to, such as a parent span.
+    pub to_span_id: i64,
+}
+
+/// The spans of a trace, with every reference between them.
+///
+/// Unlike the tree built by [`TraceGraph::to_tree`], which places each span beneath a
+/// single parent, the graph keeps each of a span's `CHILD_OF` and `FOLLOWS_FROM`
+/// references, including those to spans which were never received.
+#[derive(Clone, Debug, Default)]
+pub struct TraceGraph {
+    spans: Vec<SpanWithProcess>,
+    references: Vec<SpanReference>,
+}
+
+impl TraceGraph {
+    pub fn new(spans: impl IntoIterator<Item = SpanWithProcess>) -> Self {
+        let spans: Vec<SpanWithProcess> = spans.into_iter().collect();
+        let references = spans
+            .iter()
+            .flat_map(|span| {
+                span.references_in_trace()
+                    .into_iter()
+                    .map(|(ref_type, to_span_id)| SpanReference {
+                        ref_type,
+                        from_span_id: span.span_id,
+                        to_span_id,
+                    })
+            })
+            .collect();
+        Self { spans, references }
+    }
+
+    pub fn spans(&self) -> &[SpanWithProcess] {
+        &self.spans
+    }
+
+    pub fn span(&self, span_id: i64) -> Option<&SpanWithProcess> {
+        self.spans.iter().find(|span| span.span_id == span_id)
+    }
+
+    pub fn references(&self) -> &[SpanReference] {
+        &self.references
+    }
+
+    /// Get the spans holding a reference of the given type to the span with the given ID:
+    /// its children, for `CHILD_OF`, or the spans which follow from it, for
+    /// `FOLLOWS_FROM`.
+    pub fn referenced_by(&self, span_id: i64, ref_type: SpanRefType) -> Vec<&SpanWithProcess> {
+        self.references
+            .iter()
+            .filter(|reference| reference.ref_type == ref_type && reference.to_span_id == span_id)
+            .filter_map(|reference| self.span(reference.from_span_id))
+            .collect()
+    }
+
+    /// Get the spans which the span with the given ID holds a reference of the given type
+    /// to: its parents, for `CHILD_OF`, or the spans it follows from, for `FOLLOWS_FROM`.
+    /// Referenced spans which were not received are left out.
+    pub fn references_from(&self, span_id: i64, ref_type: SpanRefType) -> Vec<&SpanWithProcess> {
+        self.references
+            .iter()
+            .filter(|reference| reference.ref_type == ref_type && reference.from_span_id == span_id)
+            .filter_map(|reference| self.span(reference.to_span_id))
+            .collect()
+    }
+
+    /// Assemble the spans into as many trees as they form. See [`TraceForest`].
+    pub fn to_forest(&self) -> TraceForest {
+        TraceForest::new(self.spans.iter().cloned())
+    }
+
+    /// Assemble the spans into a tree. See [`Span::tree_parent_span_id`].
+    pub fn to_tree(&self) -> Result<Node<SpanWithProcess>, anyhow::Error> {
+        build_span_tree(self.spans.iter().cloned())
+    }
+}
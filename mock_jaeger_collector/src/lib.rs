@@ -1,5 +1,17 @@
 #![doc = include_str!("../README.md")]
 
+pub mod assertions;
+mod configuration;
 pub mod jaeger_models;
+pub mod rendering;
+pub mod reports;
 mod server;
-pub use server::DetachedJaegerCollectorServer;
+pub mod snapshots;
+mod span_query;
+
+pub use configuration::Configuration;
+pub use server::{
+    CollectorSession, DetachedJaegerCollectorServer, Faults, RejectedBatch, WaitForTraceError,
+    TEST_ID_ATTRIBUTE,
+};
+pub use span_query::SpanQuery;
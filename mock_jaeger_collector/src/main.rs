@@ -0,0 +1,116 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use actix_web::rt::signal;
+use anyhow::Context;
+use clap::Parser;
+use itertools::Itertools;
+use mock_jaeger_collector::jaeger_models::Batch;
+use mock_jaeger_collector::{Configuration, DetachedJaegerCollectorServer};
+use thrift::protocol::{TBinaryOutputProtocol, TOutputProtocol};
+
+/// Run a mock Jaeger collector, which stores the spans it receives in memory and serves
+/// them through Jaeger's query API.
+#[derive(Parser)]
+#[command(version, about)]
+struct Arguments {
+    /// The address to listen on.
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// The port on which to accept spans over HTTP, and serve the query API.
+    #[arg(long, default_value_t = 14268)]
+    http_port: u16,
+
+    /// The port on which to accept compact Thrift `emitBatch` packets, as the Jaeger
+    /// agent does.
+    #[arg(long, default_value_t = 6831)]
+    udp_port: u16,
+
+    /// The port on which to serve the OTLP/gRPC trace service.
+    #[arg(long, default_value_t = 4317)]
+    otlp_port: u16,
+
+    /// A directory to write each received batch to, as a binary Thrift file which can be
+    /// `POST`ed back to `/api/traces` as `application/x-thrift`.
+    #[arg(long)]
+    dump_dir: Option<PathBuf>,
+
+    /// A file to append each received batch to, which `--load` can later read back.
+    #[arg(long)]
+    persist_file: Option<PathBuf>,
+
+    /// A file of batches, written by `--persist-file`, to serve alongside those received.
+    #[arg(long)]
+    load: Option<PathBuf>,
+}
+
+#[actix_web::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let arguments = Arguments::parse();
+    if let Some(dump_dir) = &arguments.dump_dir {
+        fs::create_dir_all(dump_dir)
+            .with_context(|| format!("Failed to create {}", dump_dir.display()))?;
+    }
+
+    let config = Configuration {
+        host: arguments.host,
+        http_port: arguments.http_port,
+        udp_port: arguments.udp_port,
+        otlp_port: arguments.otlp_port,
+        persist_path: arguments.persist_file,
+    };
+    let collector = match &arguments.load {
+        Some(path) => DetachedJaegerCollectorServer::load_with_configuration(path, config),
+        None => DetachedJaegerCollectorServer::start_with_configuration(config),
+    }
+    .context("Failed to start collector")?;
+
+    let dump_dir = arguments.dump_dir;
+    let batch_count = AtomicUsize::new(0);
+    collector.on_batch_received(move |batch| {
+        let batch_number = batch_count.fetch_add(1, Ordering::Relaxed) + 1;
+        println!("Batch {}: {}", batch_number, summarise_batch(batch));
+        if let Some(dump_dir) = &dump_dir {
+            let path = dump_dir.join(format!("batch-{:06}.thrift", batch_number));
+            if let Err(error) = dump_batch(batch, &path) {
+                eprintln!("Failed to write {}: {:#}", path.display(), error);
+            }
+        }
+    });
+
+    println!(
+        "Accepting spans over HTTP at {}, as UDP packets at {}, and over OTLP/gRPC at {}",
+        collector.base_url(),
+        collector.agent_endpoint(),
+        collector.grpc_endpoint()
+    );
+    signal::ctrl_c()
+        .await
+        .context("Failed to listen for Ctrl-C")?;
+    for error in collector.persistence_errors() {
+        eprintln!("{}", error);
+    }
+    collector.shutdown()
+}
+
+/// Summarise a batch as its service name, and the number of spans and traces it contains.
+fn summarise_batch(batch: &Batch) -> String {
+    let trace_ids = batch.spans.iter().map(|span| span.hex_trace_id()).unique();
+    format!(
+        "{} spans from {}, in traces {}",
+        batch.spans.len(),
+        batch.process.service_name,
+        trace_ids.format(", ")
+    )
+}
+
+fn dump_batch(batch: &Batch, path: &Path) -> Result<(), anyhow::Error> {
+    let file = File::create(path)?;
+    let mut binary_output = TBinaryOutputProtocol::new(BufWriter::new(file), true);
+    batch.write_to_out_protocol(&mut binary_output)?;
+    binary_output.flush()?;
+    Ok(())
+}
@@ -0,0 +1,165 @@
+//! Human-readable renderings of a trace, in the style of Jaeger's waterfall view.
+//!
+//! ```ignore
+//! use mock_jaeger_collector::rendering::RenderTree;
+//!
+//! println!("{}", trace.render_tree());
+//! ```
+//!
+//! prints each span on its own line, indented beneath its parent:
+//!
+//! ```text
+//! HTTP request [test_service] (+0ns, 500µs, UNSET) http.method=GET
+//!   GET /fact [test_service] (+100µs, 200µs, UNSET) http.method=GET
+//! ```
+//!
+//! The errors of [`DetachedJaegerCollectorServer::wait_for_trace`] and of the
+//! [`assertions`] include this rendering of the trace they last saw.
+//!
+//! [`DetachedJaegerCollectorServer::wait_for_trace`]: crate::DetachedJaegerCollectorServer::wait_for_trace
+//! [`assertions`]: crate::assertions
+
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use rctree::Node;
+
+use crate::jaeger_models::{Span, SpanWithProcess, TagValue};
+
+/// The tags shown by default, when a span has them.
+pub const DEFAULT_KEY_TAGS: &[&str] = &[
+    "span.kind",
+    "http.method",
+    "http.route",
+    "http.target",
+    "http.status_code",
+    "rpc.service",
+    "rpc.method",
+    "db.system",
+    "messaging.system",
+];
+
+type Annotate<'a> = &'a dyn Fn(&Node<SpanWithProcess>) -> Option<String>;
+
+/// Renders a trace as an indented tree, one line per span, showing its operation name,
+/// its service, its start relative to the start of the trace, its duration, its status
+/// and its key tags.
+pub struct TraceTree<'a> {
+    trace: &'a Node<SpanWithProcess>,
+    key_tags: Vec<String>,
+    annotate: Option<Annotate<'a>>,
+}
+
+impl<'a> TraceTree<'a> {
+    /// Show the tags with the given keys, instead of [`DEFAULT_KEY_TAGS`].
+    pub fn with_key_tags<K: Into<String>>(mut self, keys: impl IntoIterator<Item = K>) -> Self {
+        self.key_tags = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Append the note returned by `annotate`, if any, to each span's line.
+    pub fn with_annotations(mut self, annotate: Annotate<'a>) -> Self {
+        self.annotate = Some(annotate);
+        self
+    }
+
+    fn fmt_span(
+        &self,
+        f: &mut Formatter<'_>,
+        node: &Node<SpanWithProcess>,
+        trace_start: i64,
+    ) -> fmt::Result {
+        let depth = node.ancestors().count() - self.trace.ancestors().count();
+        let span = node.borrow();
+        write!(
+            f,
+            "{:indent$}{} [{}] (+{:?}, {:?}, {})",
+            "",
+            span.operation_name,
+            span.service_name(),
+            micros(span.start_time - trace_start),
+            micros(span.duration),
+            status(&span),
+            indent = depth * 2
+        )?;
+        for key in &self.key_tags {
+            if let Some(Ok(value)) = span.get_tag(key).map(|tag| tag.value()) {
+                write!(f, " {}={}", key, DisplayTagValue(&value))?;
+            }
+        }
+        if let Some(note) = self.annotate.and_then(|annotate| annotate(node)) {
+            write!(f, "  <- {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for TraceTree<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let trace_start = self
+            .trace
+            .descendants()
+            .map(|span| span.borrow().start_time)
+            .min()
+            .unwrap_or_default();
+        for (index, span) in self.trace.descendants().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            self.fmt_span(f, &span, trace_start)?;
+        }
+        Ok(())
+    }
+}
+
+pub trait RenderTree {
+    /// Render the trace as an indented tree. See [`TraceTree`].
+    fn render_tree(&self) -> TraceTree<'_>;
+}
+
+impl RenderTree for Node<SpanWithProcess> {
+    fn render_tree(&self) -> TraceTree<'_> {
+        TraceTree {
+            trace: self,
+            key_tags: DEFAULT_KEY_TAGS.iter().map(|key| key.to_string()).collect(),
+            annotate: None,
+        }
+    }
+}
+
+pub(crate) fn micros(micros: i64) -> Duration {
+    Duration::from_micros(micros.max(0) as u64)
+}
+
+/// Describe the span's status as OpenTelemetry does, from either its `otel.status_code`
+/// tag or, as Jaeger clients report errors, its `error` tag.
+fn status(span: &Span) -> String {
+    let tag_value = |key| span.get_tag(key).and_then(|tag| tag.value().ok());
+    let status_code = tag_value("otel.status_code");
+    let is_error = tag_value("error") == Some(TagValue::Bool(true))
+        || status_code == Some(TagValue::String("ERROR"));
+    if is_error {
+        return match tag_value("otel.status_description") {
+            Some(description) => format!("ERROR: {}", DisplayTagValue(&description)),
+            None => "ERROR".into(),
+        };
+    }
+    match status_code {
+        Some(TagValue::String(code)) => code.to_owned(),
+        _ => "UNSET".into(),
+    }
+}
+
+struct DisplayTagValue<'a, 't>(&'a TagValue<'t>);
+
+impl Display for DisplayTagValue<'_, '_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            TagValue::String(value) => write!(f, "{}", value),
+            TagValue::Double(value) => write!(f, "{}", value),
+            TagValue::Bool(value) => write!(f, "{}", value),
+            TagValue::Long(value) => write!(f, "{}", value),
+            TagValue::Binary(value) => write!(f, "<{} bytes>", value.len()),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Visual reports of a trace, for attaching to pull requests or inspecting after a test
+//! fails: a Graphviz DOT graph, a Mermaid sequence diagram of the calls between
+//! services, and a self-contained HTML waterfall page.
+//!
+//! ```ignore
+//! use mock_jaeger_collector::reports::TraceReport;
+//!
+//! TraceReport::new(&trace).write("fetches_a_cat_fact")?;
+//! ```
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use rctree::Node;
+
+use crate::jaeger_models::SpanWithProcess;
+use crate::rendering::micros;
+
+/// Exports a trace in several formats.
+pub struct TraceReport<'a> {
+    trace: &'a Node<SpanWithProcess>,
+}
+
+impl<'a> TraceReport<'a> {
+    pub fn new(trace: &'a Node<SpanWithProcess>) -> Self {
+        Self { trace }
+    }
+
+    /// Render the trace as a Graphviz graph, with an edge from each span to each of its
+    /// children.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph trace {\n  rankdir=LR;\n  node [shape=box];\n");
+        for node in self.trace.descendants() {
+            let span = node.borrow();
+            let label = format!(
+                "{}\n[{}]\n{:?}",
+                span.operation_name,
+                span.service_name(),
+                micros(span.duration)
+            );
+            let _ = writeln!(dot, "  \"{:016x}\" [label={:?}];", span.span_id, label);
+            if let Some(parent) = node.parent() {
+                let _ = writeln!(
+                    dot,
+                    "  \"{:016x}\" -> \"{:016x}\";",
+                    parent.borrow().span_id,
+                    span.span_id
+                );
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render the trace as a Mermaid sequence diagram, with a participant for each
+    /// service, and each span drawn as a call from its parent's service to its own.
+    pub fn to_mermaid(&self) -> String {
+        let mut participants: Vec<String> = Vec::new();
+        for node in self.trace.descendants() {
+            let service_name = node.borrow().service_name().to_owned();
+            if !participants.contains(&service_name) {
+                participants.push(service_name);
+            }
+        }
+
+        let mut mermaid = String::from("sequenceDiagram\n");
+        for (index, service_name) in participants.iter().enumerate() {
+            let _ = writeln!(
+                mermaid,
+                "    participant s{} as {}",
+                index,
+                escape_mermaid(service_name)
+            );
+        }
+        let participant = |span: &SpanWithProcess| {
+            let service_name = span.service_name();
+            participants
+                .iter()
+                .position(|name| *name == service_name)
+                .unwrap()
+        };
+        self.write_mermaid_calls(&mut mermaid, self.trace, &participant);
+        mermaid
+    }
+
+    fn write_mermaid_calls(
+        &self,
+        mermaid: &mut String,
+        node: &Node<SpanWithProcess>,
+        participant: &dyn Fn(&SpanWithProcess) -> usize,
+    ) {
+        let span = node.borrow();
+        let callee = participant(&span);
+        let caller = node
+            .parent()
+            .map_or(callee, |parent| participant(&parent.borrow()));
+        let _ = writeln!(
+            mermaid,
+            "    s{}->>+s{}: {}",
+            caller,
+            callee,
+            escape_mermaid(&span.operation_name)
+        );
+        for child in children_by_start_time(node) {
+            self.write_mermaid_calls(mermaid, &child, participant);
+        }
+        let _ = writeln!(
+            mermaid,
+            "    s{}-->>-s{}: {:?}",
+            callee,
+            caller,
+            micros(span.duration)
+        );
+    }
+
+    /// Render the trace as a standalone HTML page, with a row for each span and a bar
+    /// showing when it ran relative to the rest of the trace.
+    pub fn to_html(&self) -> String {
+        let spans: Vec<Node<SpanWithProcess>> = self.trace.descendants().collect();
+        let trace_start = spans.iter().map(|span| span.borrow().start_time).min();
+        let trace_end = spans
+            .iter()
+            .map(|span| span.borrow().start_time + span.borrow().duration)
+            .max();
+        let (trace_start, trace_end) = (trace_start.unwrap(), trace_end.unwrap());
+        let trace_duration = (trace_end - trace_start).max(1) as f64;
+
+        let mut rows = String::new();
+        let mut write_row = |node: &Node<SpanWithProcess>, depth: usize| {
+            let span = node.borrow();
+            let _ = writeln!(
+                rows,
+                "<tr><td style=\"padding-left: {}em\">{} <span class=\"service\">{}</span></td>\
+                 <td class=\"timeline\"><div class=\"bar\" style=\"left: {:.3}%; width: {:.3}%\" \
+                 title=\"+{:?}\"></div><span>{:?}</span></td></tr>",
+                depth,
+                escape_html(&span.operation_name),
+                escape_html(span.service_name()),
+                (span.start_time - trace_start) as f64 / trace_duration * 100.0,
+                span.duration.max(0) as f64 / trace_duration * 100.0,
+                micros(span.start_time - trace_start),
+                micros(span.duration),
+            );
+        };
+        walk_by_start_time(self.trace, 0, &mut write_row);
+
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n<h1>{}</h1>\n\
+             <p>Trace {} took {:?}.</p>\n<table>\n{}</table>\n</body>\n</html>\n",
+            escape_html(&self.trace.borrow().operation_name),
+            HTML_STYLE,
+            escape_html(&self.trace.borrow().operation_name),
+            self.trace.borrow().hex_trace_id(),
+            micros(trace_end - trace_start),
+            rows
+        )
+    }
+
+    /// Write the trace in each format under [`reports_directory`], as `<name>.dot`,
+    /// `<name>.mmd` and `<name>.html`, returning the paths written.
+    pub fn write(&self, name: &str) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let directory = reports_directory();
+        fs::create_dir_all(&directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+        [
+            ("dot", self.to_dot()),
+            ("mmd", self.to_mermaid()),
+            ("html", self.to_html()),
+        ]
+        .into_iter()
+        .map(|(extension, contents)| {
+            let path = directory.join(format!("{}.{}", name, extension));
+            fs::write(&path, contents)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            Ok(path)
+        })
+        .collect()
+    }
+}
+
+/// The directory reports are written to: `trace-reports` in the Cargo target directory
+/// of the running test.
+pub fn reports_directory() -> PathBuf {
+    let target_directory = env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .or_else(|| {
+            // Test binaries are built into `<target>/<profile>/deps`.
+            let executable = env::current_exe().ok()?;
+            executable
+                .ancestors()
+                .find(|directory| directory.file_name().is_some_and(|name| name == "target"))
+                .map(PathBuf::from)
+        })
+        .unwrap_or_else(|| PathBuf::from("target"));
+    target_directory.join("trace-reports")
+}
+
+fn children_by_start_time(node: &Node<SpanWithProcess>) -> Vec<Node<SpanWithProcess>> {
+    let mut children: Vec<Node<SpanWithProcess>> = node.children().collect();
+    children.sort_by_key(|child| child.borrow().start_time);
+    children
+}
+
+fn walk_by_start_time(
+    node: &Node<SpanWithProcess>,
+    depth: usize,
+    visit: &mut dyn FnMut(&Node<SpanWithProcess>, usize),
+) {
+    visit(node, depth);
+    for child in children_by_start_time(node) {
+        walk_by_start_time(&child, depth + 1, visit);
+    }
+}
+
+/// Replace the characters which Mermaid treats as syntax with entity codes.
+fn escape_mermaid(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            ';' | '#' | ':' | '\n' => format!("#{};", c as u32),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; }
+table { border-collapse: collapse; width: 100%; }
+td { border-bottom: 1px solid #ddd; padding: 4px; white-space: nowrap; }
+.service { color: #777; }
+.timeline { position: relative; width: 60%; }
+.bar { position: absolute; top: 4px; bottom: 4px; min-width: 1px; background: #4c9be8; }
+.timeline span { position: relative; padding-left: 4px; }
+";
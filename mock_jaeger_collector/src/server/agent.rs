@@ -0,0 +1,68 @@
+use std::io;
+use std::sync::Arc;
+
+use actix_web::rt::net::UdpSocket;
+use anyhow::{anyhow, bail};
+use thrift::protocol::{field_id, TCompactInputProtocol, TInputProtocol, TType};
+
+use super::rejected::{RejectedBatch, AGENT_ENDPOINT};
+use super::store::BatchStore;
+use crate::jaeger_models::Batch;
+
+/// The size of the buffer each datagram is received into. This holds the largest UDP
+/// payload, which is 65,507 bytes over IPv4 and 65,527 bytes over IPv6, so no packet is
+/// truncated, even from clients configured with larger packets than the default of
+/// 65,000 bytes.
+const RECEIVE_BUFFER_SIZE: usize = 65_535;
+
+/// Receive compact Thrift `Agent.emitBatch` messages, as sent by the default Jaeger agent
+/// exporters, and store the batches they contain.
+pub(super) async fn run_agent(
+    socket: UdpSocket,
+    batch_store: Arc<BatchStore>,
+) -> Result<(), io::Error> {
+    let mut buffer = vec![0; RECEIVE_BUFFER_SIZE];
+    loop {
+        let (length, _) = socket.recv_from(&mut buffer).await?;
+
+        // `emitBatch` is a oneway call, so there is no way to report a malformed
+        // packet back to the client. As the real agent does, we drop it, but keep a
+        // record of it for tests to inspect.
+        match read_emit_batch(&buffer[..length]) {
+            Ok(batch) => batch_store.add([batch]),
+            Err(error) => batch_store.reject(RejectedBatch {
+                endpoint: AGENT_ENDPOINT.to_owned(),
+                content_type: None,
+                payload: buffer[..length].to_vec(),
+                status: None,
+                error: format!("{:#}", error),
+            }),
+        }
+    }
+}
+
+fn read_emit_batch(bytes: &[u8]) -> Result<Batch, anyhow::Error> {
+    let mut compact_input = TCompactInputProtocol::new(bytes);
+    let message = compact_input.read_message_begin()?;
+    if message.name != "emitBatch" {
+        bail!("Unsupported agent method: {}", message.name);
+    }
+
+    compact_input.read_struct_begin()?;
+    let mut batch = None;
+    loop {
+        let field_ident = compact_input.read_field_begin()?;
+        if field_ident.field_type == TType::Stop {
+            break;
+        }
+        match field_id(&field_ident)? {
+            1 => batch = Some(Batch::read_from_in_protocol(&mut compact_input)?),
+            _ => compact_input.skip(field_ident.field_type)?,
+        }
+        compact_input.read_field_end()?;
+    }
+    compact_input.read_struct_end()?;
+    compact_input.read_message_end()?;
+
+    batch.ok_or_else(|| anyhow!("emitBatch message did not contain a batch"))
+}
@@ -0,0 +1,96 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use tokio::sync::watch;
+use tokio::time::sleep;
+
+/// Misbehaviour to inject into a running [`DetachedJaegerCollectorServer`], to test how a
+/// service copes with a struggling tracing backend. Faults apply to the endpoints which
+/// receive spans, and never to the query API. Datagrams sent to the agent endpoint are
+/// never answered, so only `drop_every_nth` applies to them, and the other faults are
+/// ignored.
+///
+/// ```ignore
+/// collector.set_faults(Faults {
+///     status: Some(StatusCode::SERVICE_UNAVAILABLE),
+///     ..Faults::default()
+/// })?;
+/// ```
+///
+/// [`DetachedJaegerCollectorServer`]: crate::DetachedJaegerCollectorServer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Faults {
+    /// Refuse every HTTP request with this status, such as `503 Service Unavailable`,
+    /// rather than storing its spans. OTLP/gRPC exports fail with the corresponding gRPC
+    /// status. This must be a client or server error.
+    pub status: Option<StatusCode>,
+
+    /// Wait this long before handling each HTTP request or OTLP/gRPC export.
+    pub latency: Option<Duration>,
+
+    /// Accept each HTTP request and OTLP/gRPC export, but never respond to it, until the
+    /// faults are changed.
+    pub hang: bool,
+
+    /// Accept every request, or agent datagram, as usual, but silently discard every Nth
+    /// batch received since the faults were set. Batches are counted individually, so a
+    /// request carrying several, such as an OTLP export with spans from several services,
+    /// may be partly dropped, while one carrying none is not counted.
+    pub drop_every_nth: Option<usize>,
+}
+
+/// The faults currently injected into a server, shared between its transports.
+pub(crate) struct FaultInjector {
+    faults: watch::Sender<Faults>,
+    batches_received: AtomicUsize,
+}
+
+impl FaultInjector {
+    pub(crate) fn new() -> Self {
+        Self {
+            faults: watch::Sender::new(Faults::default()),
+            batches_received: AtomicUsize::new(0),
+        }
+    }
+
+    /// Replace the injected faults. Requests already hanging are released if `faults`
+    /// no longer hangs.
+    pub(crate) fn set(&self, faults: Faults) {
+        self.batches_received.store(0, Ordering::Relaxed);
+        self.faults.send_replace(faults);
+    }
+
+    pub(crate) fn get(&self) -> Faults {
+        self.faults.borrow().clone()
+    }
+
+    /// Count a received batch, returning whether it should be discarded.
+    pub(crate) fn should_drop_batch(&self) -> bool {
+        let drop_every_nth = match self.faults.borrow().drop_every_nth {
+            Some(drop_every_nth) if drop_every_nth > 0 => drop_every_nth,
+            _ => return false,
+        };
+        let received = self.batches_received.fetch_add(1, Ordering::Relaxed) + 1;
+        received.is_multiple_of(drop_every_nth)
+    }
+
+    /// Hang, then wait out the latency, as the faults require before a request is handled,
+    /// returning the status to refuse the request with, if any.
+    pub(crate) async fn delay_request(&self) -> Option<StatusCode> {
+        let mut faults = self.faults.subscribe();
+        while faults.borrow_and_update().hang {
+            // The sender lives as long as the server, so this only fails as it shuts down.
+            if faults.changed().await.is_err() {
+                break;
+            }
+        }
+        let Faults {
+            status, latency, ..
+        } = faults.borrow().clone();
+        if let Some(latency) = latency {
+            sleep(latency).await;
+        }
+        status
+    }
+}
@@ -0,0 +1,518 @@
+mod agent;
+mod faults;
+mod otlp;
+mod otlp_grpc;
+mod persistence;
+mod query;
+mod rejected;
+mod session;
+mod store;
+mod wait;
+mod zipkin;
+
+use std::io;
+use std::net::{TcpListener, UdpSocket};
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use actix_web::dev::{Decompress, Server, ServerHandle};
+use actix_web::http::{header, StatusCode};
+use actix_web::rt::{self, System};
+use actix_web::web::{get, post, BytesMut, Data, Payload};
+use actix_web::{App, HttpMessage, HttpRequest, HttpResponse, HttpServer};
+use anyhow::{anyhow, bail, Context};
+use futures_util::StreamExt;
+use rctree::Node;
+use reqwest::ClientBuilder;
+use thrift::protocol::TBinaryInputProtocol;
+
+use crate::jaeger_models::span_tree::build_span_tree;
+use crate::jaeger_models::{Batch, SpanWithProcess, TraceForest, TraceGraph};
+use crate::{Configuration, SpanQuery};
+
+pub use self::faults::Faults;
+pub use self::rejected::RejectedBatch;
+pub use self::session::{CollectorSession, TEST_ID_ATTRIBUTE};
+pub use self::wait::WaitForTraceError;
+
+use self::agent::run_agent;
+use self::otlp::post_otlp_traces_handler;
+use self::otlp_grpc::run_otlp_grpc_server;
+use self::persistence::{read_batches, BatchFileWriter};
+use self::query::{
+    get_operations_handler, get_services_handler, get_trace_handler, search_traces_handler,
+};
+use self::store::BatchStore;
+use self::wait::wait_until_trace_matches;
+use self::zipkin::{post_zipkin_v1_spans_handler, post_zipkin_v2_spans_handler};
+
+/// The `Content-Encoding`s which request bodies may be compressed with.
+const SUPPORTED_CONTENT_ENCODINGS: [&str; 4] = ["identity", "gzip", "deflate", "zstd"];
+
+/// Whether the body of a request is compressed with a supported `Content-Encoding`, if
+/// any. Requests whose bodies cannot be decoded should be rejected with a
+/// `415 Unsupported Media Type`.
+fn content_encoding_is_supported(request: &HttpRequest) -> bool {
+    request
+        .headers()
+        .get_all(header::CONTENT_ENCODING)
+        .all(|encoding| {
+            encoding.to_str().is_ok_and(|encoding| {
+                SUPPORTED_CONTENT_ENCODINGS
+                    .iter()
+                    .any(|supported| supported.eq_ignore_ascii_case(encoding.trim()))
+            })
+        })
+}
+
+/// Read the full body of a request into memory, decompressing it according to its
+/// `Content-Encoding`, as exporters and proxies may compress the spans they send.
+async fn read_payload(request: &HttpRequest, payload: Payload) -> Result<BytesMut, anyhow::Error> {
+    let mut payload = Decompress::from_headers(payload.into_inner(), request.headers());
+    let mut bytes = BytesMut::new();
+    while let Some(item) = payload.next().await {
+        bytes.extend_from_slice(&item?);
+    }
+    Ok(bytes)
+}
+
+/// Hang, wait, or fail as the injected faults require before a request receiving spans
+/// is handled, returning the response to fail it with, if any.
+async fn inject_faults(received_batches: &BatchStore) -> Option<HttpResponse> {
+    let status = received_batches.faults().delay_request().await?;
+    Some(HttpResponse::build(status).body("Injected fault"))
+}
+
+/// The content types the Jaeger collector accepts binary Thrift batches with.
+const THRIFT_CONTENT_TYPES: [&str; 2] = [
+    "application/x-thrift",
+    "application/vnd.apache.thrift.binary",
+];
+
+/// Handle `POST /api/traces`, which accepts a single batch in the binary Thrift protocol.
+/// As with the Jaeger collector, malformed batches are refused with a `400 Bad Request`.
+async fn post_traces_handler(
+    request: HttpRequest,
+    payload: Payload,
+    received_batches: Data<BatchStore>,
+) -> HttpResponse {
+    if let Some(response) = inject_faults(&received_batches).await {
+        return response;
+    }
+
+    let reject = |payload: &[u8], status: StatusCode, error: String| {
+        received_batches.reject(RejectedBatch::from_request(
+            &request,
+            payload,
+            status,
+            error.clone(),
+        ));
+        HttpResponse::build(status).body(error)
+    };
+
+    match request.mime_type() {
+        // Older Jaeger clients send batches without a content type, so take them to be
+        // binary Thrift, as the Jaeger collector does.
+        Ok(None) => (),
+        Ok(Some(mime)) if THRIFT_CONTENT_TYPES.contains(&mime.essence_str()) => (),
+        Ok(Some(mime)) => {
+            let error = format!("Unsupported content type: {}", mime);
+            return reject(&[], StatusCode::UNSUPPORTED_MEDIA_TYPE, error);
+        }
+        Err(_) => {
+            let error = "Invalid content type".to_owned();
+            return reject(&[], StatusCode::UNSUPPORTED_MEDIA_TYPE, error);
+        }
+    }
+    if !content_encoding_is_supported(&request) {
+        let error = "Unsupported content encoding".to_owned();
+        return reject(&[], StatusCode::UNSUPPORTED_MEDIA_TYPE, error);
+    }
+
+    let bytes = match read_payload(&request, payload).await {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            let error = format!("Unable to read request body: {:#}", error);
+            return reject(&[], StatusCode::INTERNAL_SERVER_ERROR, error);
+        }
+    };
+    let mut binary_input = TBinaryInputProtocol::new(bytes.as_ref(), false);
+    match Batch::read_from_in_protocol(&mut binary_input) {
+        Ok(batch) => {
+            received_batches.add([batch]);
+            HttpResponse::Accepted().finish()
+        }
+        Err(error) => {
+            let error = format!("Unable to process request body: {}", error);
+            reject(&bytes, StatusCode::BAD_REQUEST, error)
+        }
+    }
+}
+
+fn run_server(listener: TcpListener, batch_store: Arc<BatchStore>) -> Result<Server, io::Error> {
+    let batch_store = Data::from(batch_store);
+
+    Ok(HttpServer::new(move || {
+        App::new()
+            .app_data(batch_store.clone())
+            .route("/up", get().to(HttpResponse::Ok))
+            .route("/api/traces", post().to(post_traces_handler))
+            .route("/api/traces", get().to(search_traces_handler))
+            .route("/api/traces/{trace_id}", get().to(get_trace_handler))
+            .route("/api/services", get().to(get_services_handler))
+            .route(
+                "/api/services/{service}/operations",
+                get().to(get_operations_handler),
+            )
+            .route("/v1/traces", post().to(post_otlp_traces_handler))
+            .route("/api/v1/spans", post().to(post_zipkin_v1_spans_handler))
+            .route("/api/v2/spans", post().to(post_zipkin_v2_spans_handler))
+    })
+    .listen(listener)?
+    .run())
+}
+
+/// A mock Jaeger collector, which stores the spans it receives in memory until it is shut
+/// down or dropped.
+///
+/// The server accepts spans over each of these transports, translating them into the
+/// Jaeger model and storing them together:
+///
+/// - binary Thrift batches, `POST`ed to `/api/traces` with a `Content-Type` of
+///   `application/x-thrift` or `application/vnd.apache.thrift.binary`, or with none, as
+///   the Jaeger collector accepts them;
+/// - compact Thrift `emitBatch` datagrams, sent to the [`agent_endpoint`], as the Jaeger
+///   agent accepts them;
+/// - OTLP/HTTP export requests, `POST`ed to `/v1/traces` as either protobuf or JSON
+///   according to their `Content-Type`;
+/// - OTLP/gRPC `TraceService/Export` calls, made to the [`grpc_endpoint`];
+/// - Zipkin v2 spans, `POST`ed to `/api/v2/spans` as either JSON or protobuf, and legacy
+///   Zipkin v1 Thrift spans, `POST`ed to `/api/v1/spans`.
+///
+/// Paths are relative to the [`base_url`]. HTTP request bodies may be compressed with a
+/// `Content-Encoding` of `gzip`, `deflate` or `zstd`, and OTLP/gRPC messages with `gzip`
+/// or `zstd`. As the Jaeger collector does, the server accepts Jaeger and Zipkin batches
+/// with a `202 Accepted`, refuses malformed bodies with a `400 Bad Request`, and refuses
+/// bodies of an unsupported content type or encoding with a `415 Unsupported Media Type`.
+/// OTLP/HTTP requests are answered with the statuses OTLP requires instead. Everything
+/// refused is recorded in [`rejected_batches`].
+///
+/// The stored traces are served through the same HTTP API as Jaeger's query service, at
+/// `/api/services`, `/api/services/{service}/operations`, `/api/traces` and
+/// `/api/traces/{trace_id}`, so a Jaeger UI whose query base URL is the [`base_url`] can
+/// browse them.
+///
+/// [`agent_endpoint`]: DetachedJaegerCollectorServer::agent_endpoint
+/// [`grpc_endpoint`]: DetachedJaegerCollectorServer::grpc_endpoint
+/// [`base_url`]: DetachedJaegerCollectorServer::base_url
+/// [`rejected_batches`]: DetachedJaegerCollectorServer::rejected_batches
+pub struct DetachedJaegerCollectorServer {
+    base_url: String,
+    agent_endpoint: String,
+    grpc_endpoint: String,
+    batch_store: Arc<BatchStore>,
+    persistence_errors: Arc<Mutex<Vec<String>>>,
+    server_handle: ServerHandle,
+    thread: Option<JoinHandle<Result<(), io::Error>>>,
+}
+
+impl DetachedJaegerCollectorServer {
+    /// Start a new detached Jaeger collector server, listening on a randomly allocated port.
+    /// Alongside the HTTP collector endpoint, the server also listens for UDP packets on a
+    /// second randomly allocated port, emulating the Jaeger agent, and serves the OTLP/gRPC
+    /// trace service on a third.
+    /// This server runs on a dedicated thread with its own runtime, rather than simply in its
+    /// own task inside the current runtime. This allows it to be started once from within a
+    /// the current runtime, while avoiding it being shut down when the main runtime is dropped.
+    /// The server runs until it is stopped with [`DetachedJaegerCollectorServer::shutdown`],
+    /// or dropped.
+    ///
+    /// This server is not intended to be used in production, but rather as a mock for testing.
+    pub fn start() -> Result<Self, anyhow::Error> {
+        Self::start_with_configuration(Configuration::default())
+    }
+
+    /// Start a new detached Jaeger collector server, as with
+    /// [`DetachedJaegerCollectorServer::start`], listening on the host and ports given by
+    /// `config`.
+    pub fn start_with_configuration(config: Configuration) -> Result<Self, anyhow::Error> {
+        Self::start_with_batches(config, Vec::new())
+    }
+
+    /// Start a new detached Jaeger collector server, as with
+    /// [`DetachedJaegerCollectorServer::start`], whose store holds the batches persisted
+    /// to `path` by a server configured with a [`Configuration::persist_path`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        Self::load_with_configuration(path, Configuration::default())
+    }
+
+    /// Start a new detached Jaeger collector server, as with
+    /// [`DetachedJaegerCollectorServer::load`], listening on the host and ports given by
+    /// `config`.
+    pub fn load_with_configuration(
+        path: impl AsRef<Path>,
+        config: Configuration,
+    ) -> Result<Self, anyhow::Error> {
+        let batches = read_batches(path.as_ref())?;
+        Self::start_with_batches(config, batches)
+    }
+
+    fn start_with_batches(
+        config: Configuration,
+        batches: Vec<Batch>,
+    ) -> Result<Self, anyhow::Error> {
+        let address = format!("{}:{}", config.host, config.http_port);
+        let listener = TcpListener::bind(&address)
+            .with_context(|| format!("Failed to bind to {}", address))?;
+
+        let address = format!("{}:{}", config.host, config.udp_port);
+        let agent_socket =
+            UdpSocket::bind(&address).with_context(|| format!("Failed to bind to {}", address))?;
+        agent_socket
+            .set_nonblocking(true)
+            .context("Failed to make agent socket non-blocking")?;
+
+        let address = format!("{}:{}", config.host, config.otlp_port);
+        let grpc_listener = TcpListener::bind(&address)
+            .with_context(|| format!("Failed to bind to {}", address))?;
+        grpc_listener
+            .set_nonblocking(true)
+            .context("Failed to make gRPC listener non-blocking")?;
+
+        let batch_store = Arc::new(BatchStore::new(batches));
+        let persistence_errors = Arc::new(Mutex::new(Vec::new()));
+        if let Some(persist_path) = &config.persist_path {
+            let writer = BatchFileWriter::open(persist_path)?;
+            let observed_persistence_errors = persistence_errors.clone();
+            batch_store.observe(move |batch| {
+                // Batches are persisted as they are received, so there is no caller to
+                // return a failure to. Keep it to be reported by `persistence_errors` instead.
+                if let Err(error) = writer.append(batch) {
+                    let error = format!("Failed to persist batch: {:#}", error);
+                    observed_persistence_errors.lock().unwrap().push(error);
+                }
+            });
+        }
+        let host = config.host;
+        let base_url = format!("http://{}:{}", host, listener.local_addr()?.port());
+        let agent_endpoint = format!("{}:{}", host, agent_socket.local_addr()?.port());
+        let grpc_endpoint = format!("http://{}:{}", host, grpc_listener.local_addr()?.port());
+
+        let (handle_sender, handle_receiver) = mpsc::channel();
+        let thread_batch_store = batch_store.clone();
+        let thread = thread::spawn(move || {
+            System::new().block_on(async move {
+                let agent_socket = rt::net::UdpSocket::from_std(agent_socket)?;
+                rt::spawn(run_agent(agent_socket, thread_batch_store.clone()));
+
+                let grpc_listener = rt::net::TcpListener::from_std(grpc_listener)?;
+                rt::spawn(run_otlp_grpc_server(
+                    grpc_listener,
+                    thread_batch_store.clone(),
+                ));
+
+                // The agent and gRPC tasks are dropped along with the runtime once the
+                // HTTP server stops.
+                let server = run_server(listener, thread_batch_store)?;
+                let _ = handle_sender.send(server.handle());
+                server.await
+            })
+        });
+
+        let server_handle = match handle_receiver.recv() {
+            Ok(server_handle) => server_handle,
+            Err(_) => {
+                return match thread.join() {
+                    Ok(Err(error)) => Err(error).context("Failed to start server"),
+                    _ => Err(anyhow!("Server thread terminated unexpectedly")),
+                }
+            }
+        };
+
+        Ok(Self {
+            base_url,
+            agent_endpoint,
+            grpc_endpoint,
+            batch_store,
+            persistence_errors,
+            server_handle,
+            thread: Some(thread),
+        })
+    }
+
+    /// Stop the server, waiting for its thread to finish. Dropping the server does the
+    /// same, but discards any error.
+    pub fn shutdown(mut self) -> Result<(), anyhow::Error> {
+        self.stop()
+    }
+
+    /// Test whether the server has started successfully.
+    pub async fn ping(&self) -> Result<(), anyhow::Error> {
+        let reqwest_client = ClientBuilder::new()
+            .build()
+            .context("Failed to build reqwest client")?;
+
+        let url = format!("{}/up", self.base_url());
+        let _ = reqwest_client.get(url).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    /// Get the base URL of the server.
+    pub fn base_url(&self) -> String {
+        self.base_url.to_owned()
+    }
+
+    /// Get the `host:port` address on which the server accepts compact Thrift
+    /// `emitBatch` UDP packets, as a Jaeger agent would.
+    pub fn agent_endpoint(&self) -> String {
+        self.agent_endpoint.to_owned()
+    }
+
+    /// Get the URL of the OTLP/gRPC trace service, in the form expected by OTLP exporters.
+    pub fn grpc_endpoint(&self) -> String {
+        self.grpc_endpoint.to_owned()
+    }
+
+    /// Retrieve a trace, in the form of a [`rctree::Node<SpanWithProcess>`], from the in-memory
+    /// store of received [`Span`]s. Each span is placed beneath its `parent_span_id`, or
+    /// failing that the span it refers to through a `CHILD_OF` or `FOLLOWS_FROM` reference,
+    /// as the Jaeger UI places them. This fails unless the spans form a single, complete
+    /// tree; see [`get_trace_forest`] for partial traces.
+    ///
+    /// [`get_trace_forest`]: DetachedJaegerCollectorServer::get_trace_forest
+    pub async fn get_trace(&self, trace_id: &str) -> Result<Node<SpanWithProcess>, anyhow::Error> {
+        self.build_trace(trace_id)
+    }
+
+    /// Find every received span matching `query`.
+    pub fn find_spans(&self, query: &SpanQuery) -> Vec<SpanWithProcess> {
+        self.batch_store.find_spans(query, |_, _| true)
+    }
+
+    /// Find every received trace containing a span matching `query`, ordered by the time
+    /// the traces started. Traces are assembled as [`TraceForest`]s, so that partial
+    /// traces are found too.
+    pub fn find_traces(&self, query: &SpanQuery) -> Vec<TraceForest> {
+        self.batch_store.find_traces(query, |_, _| true)
+    }
+
+    /// Retrieve a trace as a [`TraceForest`], which, unlike [`get_trace`], tolerates
+    /// traces with several roots, or with spans whose parents were not received.
+    ///
+    /// [`get_trace`]: DetachedJaegerCollectorServer::get_trace
+    pub fn get_trace_forest(&self, trace_id: &str) -> TraceForest {
+        TraceForest::new(self.batch_store.trace_spans(trace_id, |_| true))
+    }
+
+    /// Retrieve a trace as a [`TraceGraph`], with every `CHILD_OF` and `FOLLOWS_FROM`
+    /// reference between its spans.
+    pub fn get_trace_graph(&self, trace_id: &str) -> TraceGraph {
+        TraceGraph::new(self.batch_store.trace_spans(trace_id, |_| true))
+    }
+
+    /// Wait for a trace to be received which satisfies `predicate`, returning it as soon
+    /// as it does. The predicate is checked against the trace as it currently stands, and
+    /// again each time new spans are received, until `timeout` elapses.
+    ///
+    /// This must be awaited from within a Tokio runtime, such as that of `actix-rt`.
+    pub async fn wait_for_trace<F>(
+        &self,
+        trace_id: &str,
+        predicate: F,
+        timeout: Duration,
+    ) -> Result<Node<SpanWithProcess>, WaitForTraceError>
+    where
+        F: Fn(&Node<SpanWithProcess>) -> Result<(), anyhow::Error>,
+    {
+        wait_until_trace_matches(
+            &self.batch_store,
+            trace_id,
+            || self.build_trace(trace_id),
+            predicate,
+            timeout,
+        )
+        .await
+    }
+
+    /// Inject faults into the endpoints which receive spans, replacing any injected
+    /// before. See [`Faults`]. This fails if the faults would refuse requests with a
+    /// status which is not an error.
+    pub fn set_faults(&self, faults: Faults) -> Result<(), anyhow::Error> {
+        if let Some(status) = faults.status {
+            if !status.is_client_error() && !status.is_server_error() {
+                bail!(
+                    "Requests can only be refused with client or server errors, not {}",
+                    status
+                );
+            }
+        }
+        self.batch_store.faults().set(faults);
+        Ok(())
+    }
+
+    /// Get the faults currently injected.
+    pub fn faults(&self) -> Faults {
+        self.batch_store.faults().get()
+    }
+
+    /// Stop injecting faults, releasing any requests left hanging.
+    pub fn clear_faults(&self) {
+        self.batch_store.faults().set(Faults::default());
+    }
+
+    /// Get every request, OTLP/gRPC export or agent datagram which the collector refused
+    /// to store, such as malformed batches, or those sent with an unsupported content
+    /// type. A test can check this is empty to be sure its exporter sent nothing the real
+    /// backend would drop.
+    pub fn rejected_batches(&self) -> Vec<RejectedBatch> {
+        self.batch_store.rejected_batches()
+    }
+
+    /// Describe each batch which could not be written to the
+    /// [`Configuration::persist_path`] file, oldest first. Such batches are still stored
+    /// in memory, so are available to tests as usual.
+    pub fn persistence_errors(&self) -> Vec<String> {
+        self.persistence_errors.lock().unwrap().clone()
+    }
+
+    /// Call `observer` with every batch received from now on, over any transport, before
+    /// it is stored.
+    pub fn on_batch_received(&self, observer: impl Fn(&Batch) + Send + 'static) {
+        self.batch_store.observe(observer);
+    }
+
+    /// Open a session, giving a test its own view of the received traces. See
+    /// [`CollectorSession`].
+    pub fn open_session(&self) -> CollectorSession {
+        CollectorSession::new(self.batch_store.clone())
+    }
+
+    fn build_trace(&self, trace_id: &str) -> Result<Node<SpanWithProcess>, anyhow::Error> {
+        build_span_tree(self.batch_store.trace_spans(trace_id, |_| true))
+    }
+
+    fn stop(&mut self) -> Result<(), anyhow::Error> {
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return Ok(()),
+        };
+
+        // The stop command is sent as soon as `stop` is called. Rather than awaiting the
+        // returned future, which would require a runtime, we wait for the thread to end.
+        drop(self.server_handle.stop(false));
+        thread
+            .join()
+            .map_err(|_| anyhow!("Server thread panicked"))?
+            .context("Server failed")
+    }
+}
+
+impl Drop for DetachedJaegerCollectorServer {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
@@ -0,0 +1,384 @@
+use actix_web::http::StatusCode as HttpStatusCode;
+use actix_web::web::{Data, Payload};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use anyhow::{anyhow, Context};
+use opentelemetry_proto::tonic::collector::trace::v1::{
+    ExportTraceServiceRequest, ExportTraceServiceResponse,
+};
+use opentelemetry_proto::tonic::common::v1::any_value::Value;
+use opentelemetry_proto::tonic::common::v1::{AnyValue, KeyValue};
+use opentelemetry_proto::tonic::trace::v1::span::{Event, Link, SpanKind};
+use opentelemetry_proto::tonic::trace::v1::status::StatusCode;
+use opentelemetry_proto::tonic::trace::v1::{ResourceSpans, Span as OtlpSpan};
+use prost::Message;
+use serde::Serialize;
+use serde_json::json;
+use thrift::OrderedFloat;
+
+use super::rejected::RejectedBatch;
+use super::store::BatchStore;
+use super::{content_encoding_is_supported, inject_faults, read_payload};
+use crate::jaeger_models::{Batch, Log, Process, Span, SpanRef, SpanRefType, Tag, TagType};
+
+/// The service name Jaeger assigns to resources that do not declare a `service.name`.
+const EMPTY_SERVICE_NAME: &str = "OTLP_EMPTY_SERVICE_NAME";
+
+/// The `google.rpc.Code` that OTLP receivers report for malformed requests.
+const INVALID_ARGUMENT: i32 = 3;
+
+/// The `google.rpc.Code` that OTLP receivers report when they fail to handle a request.
+const INTERNAL: i32 = 13;
+
+/// The `google.rpc.Status` message OTLP/HTTP receivers return in the body of
+/// failed requests, encoded in the same way as the request was.
+#[derive(Clone, PartialEq, Message, Serialize)]
+struct RpcStatus {
+    #[prost(int32, tag = "1")]
+    code: i32,
+    #[prost(string, tag = "2")]
+    message: String,
+}
+
+/// The encodings OTLP/HTTP allows export requests to be sent with.
+#[derive(Clone, Copy)]
+enum OtlpEncoding {
+    Protobuf,
+    Json,
+}
+
+impl OtlpEncoding {
+    fn from_request(request: &HttpRequest) -> Option<Self> {
+        match request.mime_type().ok()??.essence_str() {
+            "application/x-protobuf" => Some(Self::Protobuf),
+            "application/json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> Result<ExportTraceServiceRequest, anyhow::Error> {
+        match self {
+            Self::Protobuf => ExportTraceServiceRequest::decode(bytes)
+                .context("Failed to decode protobuf export request"),
+            Self::Json => {
+                serde_json::from_slice(bytes).context("Failed to decode JSON export request")
+            }
+        }
+    }
+
+    fn respond<M: Message + Serialize>(self, status: HttpStatusCode, message: &M) -> HttpResponse {
+        let mut response = HttpResponse::build(status);
+        match self {
+            Self::Protobuf => response
+                .content_type("application/x-protobuf")
+                .body(message.encode_to_vec()),
+            Self::Json => response.json(message),
+        }
+    }
+}
+
+pub(super) async fn post_otlp_traces_handler(
+    request: HttpRequest,
+    payload: Payload,
+    received_batches: Data<BatchStore>,
+) -> HttpResponse {
+    if let Some(response) = inject_faults(&received_batches).await {
+        return response;
+    }
+
+    // Once the request's encoding is known, failures are described by a `google.rpc.Status`
+    // in the same encoding, as OTLP/HTTP requires.
+    let reject = |encoding: Option<OtlpEncoding>,
+                  payload: &[u8],
+                  status: HttpStatusCode,
+                  message: String| {
+        received_batches.reject(RejectedBatch::from_request(
+            &request,
+            payload,
+            status,
+            message.clone(),
+        ));
+        match encoding {
+            Some(encoding) => {
+                let code = if status.is_client_error() {
+                    INVALID_ARGUMENT
+                } else {
+                    INTERNAL
+                };
+                encoding.respond(status, &RpcStatus { code, message })
+            }
+            None => HttpResponse::build(status).body(message),
+        }
+    };
+
+    let encoding = match OtlpEncoding::from_request(&request) {
+        Some(encoding) => encoding,
+        None => {
+            let error = "Unsupported content type".to_owned();
+            return reject(None, &[], HttpStatusCode::UNSUPPORTED_MEDIA_TYPE, error);
+        }
+    };
+    if !content_encoding_is_supported(&request) {
+        let error = "Unsupported content encoding".to_owned();
+        return reject(
+            Some(encoding),
+            &[],
+            HttpStatusCode::UNSUPPORTED_MEDIA_TYPE,
+            error,
+        );
+    }
+
+    let bytes = match read_payload(&request, payload).await {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            let error = format!("Unable to read request body: {:#}", error);
+            return reject(
+                Some(encoding),
+                &[],
+                HttpStatusCode::INTERNAL_SERVER_ERROR,
+                error,
+            );
+        }
+    };
+
+    match encoding.decode(&bytes).and_then(batches_from_otlp) {
+        Ok(batches) => {
+            received_batches.add(batches);
+            encoding.respond(HttpStatusCode::OK, &ExportTraceServiceResponse::default())
+        }
+        Err(error) => reject(
+            Some(encoding),
+            &bytes,
+            HttpStatusCode::BAD_REQUEST,
+            format!("{:#}", error),
+        ),
+    }
+}
+
+/// Translate an OTLP export request into Jaeger batches, one per resource, following
+/// the same conventions as the Jaeger collector's own OTLP receiver. This fails if any
+/// span or link has a malformed trace or span ID.
+pub(super) fn batches_from_otlp(
+    request: ExportTraceServiceRequest,
+) -> Result<Vec<Batch>, anyhow::Error> {
+    request
+        .resource_spans
+        .into_iter()
+        .map(batch_from_resource_spans)
+        .collect()
+}
+
+fn batch_from_resource_spans(resource_spans: ResourceSpans) -> Result<Batch, anyhow::Error> {
+    let mut service_name = EMPTY_SERVICE_NAME.to_owned();
+    let mut process_tags = Vec::new();
+    for attribute in resource_spans
+        .resource
+        .map(|r| r.attributes)
+        .unwrap_or_default()
+    {
+        match attribute.value.and_then(|v| v.value) {
+            Some(Value::StringValue(name)) if attribute.key == "service.name" => {
+                service_name = name
+            }
+            value => process_tags.push(tag_from_value(attribute.key, value)),
+        }
+    }
+
+    let mut spans = Vec::new();
+    for scope_spans in resource_spans.scope_spans {
+        let mut scope_tags = Vec::new();
+        if let Some(scope) = scope_spans.scope {
+            if !scope.name.is_empty() {
+                scope_tags.push(Tag::string("otel.library.name", scope.name));
+            }
+            if !scope.version.is_empty() {
+                scope_tags.push(Tag::string("otel.library.version", scope.version));
+            }
+        }
+        for span in scope_spans.spans {
+            let name = span.name.clone();
+            spans.push(
+                span_from_otlp(span, &scope_tags)
+                    .with_context(|| format!("Invalid span {:?}", name))?,
+            );
+        }
+    }
+
+    Ok(Batch::new(
+        Process::new(service_name, non_empty(process_tags)),
+        spans,
+        None,
+        None,
+    ))
+}
+
+fn span_from_otlp(span: OtlpSpan, scope_tags: &[Tag]) -> Result<Span, anyhow::Error> {
+    let (trace_id_high, trace_id_low) = trace_id_from_bytes(&span.trace_id)?;
+
+    let mut tags: Vec<Tag> = span
+        .attributes
+        .into_iter()
+        .map(tag_from_key_value)
+        .collect();
+    tags.extend(scope_tags.iter().cloned());
+    if let Some(kind) = span_kind_name(span.kind) {
+        tags.push(Tag::string("span.kind", kind));
+    }
+    if let Some(status) = span.status {
+        match StatusCode::try_from(status.code) {
+            Ok(StatusCode::Ok) => tags.push(Tag::string("otel.status_code", "OK")),
+            Ok(StatusCode::Error) => {
+                tags.push(Tag::string("otel.status_code", "ERROR"));
+                tags.push(bool_tag("error", true));
+            }
+            _ => (),
+        }
+        if !status.message.is_empty() {
+            tags.push(Tag::string("otel.status_description", status.message));
+        }
+    }
+
+    let logs: Vec<Log> = span.events.into_iter().map(log_from_event).collect();
+    let references = span
+        .links
+        .into_iter()
+        .map(reference_from_link)
+        .collect::<Result<Vec<SpanRef>, _>>()?;
+
+    Ok(Span::new(
+        trace_id_low,
+        trace_id_high,
+        span_id_from_bytes(&span.span_id)?,
+        parent_span_id_from_bytes(&span.parent_span_id)?,
+        span.name,
+        non_empty(references),
+        1,
+        nanos_to_micros(span.start_time_unix_nano),
+        nanos_to_micros(
+            span.end_time_unix_nano
+                .saturating_sub(span.start_time_unix_nano),
+        ),
+        non_empty(tags),
+        non_empty(logs),
+    ))
+}
+
+fn log_from_event(event: Event) -> Log {
+    let mut fields = vec![Tag::string("event", event.name)];
+    fields.extend(event.attributes.into_iter().map(tag_from_key_value));
+    Log::new(nanos_to_micros(event.time_unix_nano), fields)
+}
+
+fn reference_from_link(link: Link) -> Result<SpanRef, anyhow::Error> {
+    let (trace_id_high, trace_id_low) =
+        trace_id_from_bytes(&link.trace_id).context("Invalid link")?;
+    Ok(SpanRef::new(
+        SpanRefType::FOLLOWS_FROM,
+        trace_id_low,
+        trace_id_high,
+        span_id_from_bytes(&link.span_id).context("Invalid link")?,
+    ))
+}
+
+fn span_kind_name(kind: i32) -> Option<&'static str> {
+    match SpanKind::try_from(kind).ok()? {
+        SpanKind::Internal => Some("internal"),
+        SpanKind::Server => Some("server"),
+        SpanKind::Client => Some("client"),
+        SpanKind::Producer => Some("producer"),
+        SpanKind::Consumer => Some("consumer"),
+        SpanKind::Unspecified => None,
+    }
+}
+
+fn tag_from_key_value(key_value: KeyValue) -> Tag {
+    tag_from_value(key_value.key, key_value.value.and_then(|v| v.value))
+}
+
+fn tag_from_value(key: String, value: Option<Value>) -> Tag {
+    match value {
+        Some(Value::StringValue(value)) => Tag::string(key, value),
+        Some(Value::BoolValue(value)) => bool_tag(key, value),
+        Some(Value::IntValue(value)) => Tag::new(key, TagType::LONG, None, None, None, value, None),
+        Some(Value::DoubleValue(value)) => Tag::new(
+            key,
+            TagType::DOUBLE,
+            None,
+            OrderedFloat(value),
+            None,
+            None,
+            None,
+        ),
+        Some(Value::BytesValue(value)) => {
+            Tag::new(key, TagType::BINARY, None, None, None, None, value)
+        }
+        // Jaeger has no structured tag types, so arrays and maps are stored as JSON strings.
+        value @ Some(Value::ArrayValue(_)) | value @ Some(Value::KvlistValue(_)) => {
+            Tag::string(key, json_from_value(value).to_string())
+        }
+        None => Tag::string(key, ""),
+    }
+}
+
+fn json_from_value(value: Option<Value>) -> serde_json::Value {
+    match value {
+        Some(Value::StringValue(value)) => json!(value),
+        Some(Value::BoolValue(value)) => json!(value),
+        Some(Value::IntValue(value)) => json!(value),
+        Some(Value::DoubleValue(value)) => json!(value),
+        Some(Value::BytesValue(value)) => json!(value),
+        Some(Value::ArrayValue(array)) => array
+            .values
+            .into_iter()
+            .map(|v: AnyValue| json_from_value(v.value))
+            .collect(),
+        Some(Value::KvlistValue(list)) => list
+            .values
+            .into_iter()
+            .map(|kv| (kv.key, json_from_value(kv.value.and_then(|v| v.value))))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        None => serde_json::Value::Null,
+    }
+}
+
+fn bool_tag(key: impl Into<String>, value: bool) -> Tag {
+    Tag::new(key.into(), TagType::BOOL, None, None, value, None, None)
+}
+
+/// Split a 16 byte OTLP trace ID into its high and low halves.
+fn trace_id_from_bytes(bytes: &[u8]) -> Result<(i64, i64), anyhow::Error> {
+    let bytes = bytes
+        .try_into()
+        .map_err(|_| anyhow!("Trace IDs must be 16 bytes long, not {}", bytes.len()))?;
+    let id = u128::from_be_bytes(bytes);
+    Ok(((id >> 64) as i64, id as i64))
+}
+
+/// Convert an 8 byte OTLP span ID into a Jaeger span ID.
+fn span_id_from_bytes(bytes: &[u8]) -> Result<i64, anyhow::Error> {
+    bytes
+        .try_into()
+        .map(i64::from_be_bytes)
+        .map_err(|_| anyhow!("Span IDs must be 8 bytes long, not {}", bytes.len()))
+}
+
+/// Convert an OTLP parent span ID into a Jaeger span ID. Root spans have an empty parent
+/// span ID, which becomes 0.
+fn parent_span_id_from_bytes(bytes: &[u8]) -> Result<i64, anyhow::Error> {
+    if bytes.is_empty() {
+        return Ok(0);
+    }
+    span_id_from_bytes(bytes).context("Invalid parent span ID")
+}
+
+fn nanos_to_micros(nanos: u64) -> i64 {
+    (nanos / 1_000) as i64
+}
+
+fn non_empty<T>(items: Vec<T>) -> Option<Vec<T>> {
+    if items.is_empty() {
+        None
+    } else {
+        Some(items)
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use actix_web::http::StatusCode;
+use actix_web::rt::net::TcpListener;
+use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::{
+    TraceService, TraceServiceServer,
+};
+use opentelemetry_proto::tonic::collector::trace::v1::{
+    ExportTraceServiceRequest, ExportTraceServiceResponse,
+};
+use prost::Message;
+use tonic::codec::CompressionEncoding;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use super::otlp::batches_from_otlp;
+use super::rejected::RejectedBatch;
+use super::store::BatchStore;
+
+struct OtlpTraceService {
+    batch_store: Arc<BatchStore>,
+}
+
+#[tonic::async_trait]
+impl TraceService for OtlpTraceService {
+    async fn export(
+        &self,
+        request: Request<ExportTraceServiceRequest>,
+    ) -> Result<Response<ExportTraceServiceResponse>, Status> {
+        if let Some(status) = self.batch_store.faults().delay_request().await {
+            return Err(grpc_status(status));
+        }
+        let request = request.into_inner();
+        // Translating the request consumes it, so keep its encoding in case it is refused
+        let payload = request.encode_to_vec();
+        let batches = match batches_from_otlp(request) {
+            Ok(batches) => batches,
+            Err(error) => {
+                let error = format!("{:#}", error);
+                self.batch_store
+                    .reject(RejectedBatch::from_grpc_export(payload, error.clone()));
+                return Err(Status::invalid_argument(error));
+            }
+        };
+        self.batch_store.add(batches);
+        Ok(Response::new(ExportTraceServiceResponse::default()))
+    }
+}
+
+/// The gRPC status an OTLP/gRPC receiver fails with where an OTLP/HTTP receiver would
+/// respond with the given HTTP status.
+fn grpc_status(http_status: StatusCode) -> Status {
+    let message = format!("Injected fault: HTTP status {}", http_status);
+    match http_status.as_u16() {
+        429 => Status::resource_exhausted(message),
+        502..=504 => Status::unavailable(message),
+        _ => Status::internal(message),
+    }
+}
+
+/// Serve the OTLP/gRPC `TraceService`, storing exported spans alongside those received
+/// over every other transport.
+pub(super) async fn run_otlp_grpc_server(
+    listener: TcpListener,
+    batch_store: Arc<BatchStore>,
+) -> Result<(), anyhow::Error> {
+    let incoming =
+        TcpIncoming::from_listener(listener, true, None).map_err(|error| anyhow::anyhow!(error))?;
+    Server::builder()
+        .add_service(
+            TraceServiceServer::new(OtlpTraceService { batch_store })
+                .accept_compressed(CompressionEncoding::Gzip)
+                .accept_compressed(CompressionEncoding::Zstd),
+        )
+        .serve_with_incoming(incoming)
+        .await?;
+    Ok(())
+}
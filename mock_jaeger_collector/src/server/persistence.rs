@@ -0,0 +1,87 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{bail, Context};
+use thrift::protocol::{TBinaryInputProtocol, TBinaryOutputProtocol, TOutputProtocol};
+
+use crate::jaeger_models::Batch;
+
+/// The largest batch, in bytes, read from a file of batches. A longer length is taken to
+/// mean the file is corrupt, rather than trusted to allocate a buffer.
+const MAX_BATCH_SIZE: u32 = 64 * 1024 * 1024;
+
+/// Appends batches to a file, each written as its length, as a big-endian `u32`, followed
+/// by its binary Thrift encoding.
+pub(super) struct BatchFileWriter {
+    file: Mutex<BufWriter<File>>,
+}
+
+impl BatchFileWriter {
+    /// Open a file for appending batches to, creating it if it does not exist.
+    pub(super) fn open(path: &Path) -> Result<Self, anyhow::Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        Ok(Self {
+            file: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    pub(super) fn append(&self, batch: &Batch) -> Result<(), anyhow::Error> {
+        let mut bytes = Vec::new();
+        let mut binary_output = TBinaryOutputProtocol::new(&mut bytes, true);
+        batch.write_to_out_protocol(&mut binary_output)?;
+        binary_output.flush()?;
+
+        // Each batch is flushed as soon as it is written, so that a file left by a test
+        // process which crashed holds every batch it received.
+        let mut file = self.file.lock().unwrap();
+        file.write_all(&u32::try_from(bytes.len())?.to_be_bytes())?;
+        file.write_all(&bytes)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+/// Read every batch from a file written by a [`BatchFileWriter`]. A final batch which was
+/// only partially written is ignored.
+pub(super) fn read_batches(path: &Path) -> Result<Vec<Batch>, anyhow::Error> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let mut batches = Vec::new();
+    loop {
+        let mut length = [0; 4];
+        let length = match reader.read_exact(&mut length) {
+            Ok(()) => u32::from_be_bytes(length),
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(error) => return Err(error.into()),
+        };
+        if length > MAX_BATCH_SIZE {
+            bail!(
+                "Batch {} is said to be {} bytes long, more than the limit of {} bytes, so {} \
+                 is probably corrupt",
+                batches.len() + 1,
+                length,
+                MAX_BATCH_SIZE,
+                path.display()
+            );
+        }
+        let mut bytes = Vec::new();
+        (&mut reader)
+            .take(u64::from(length))
+            .read_to_end(&mut bytes)?;
+        if bytes.len() < length as usize {
+            break;
+        }
+
+        let mut binary_input = TBinaryInputProtocol::new(bytes.as_slice(), true);
+        let batch = Batch::read_from_in_protocol(&mut binary_input)
+            .with_context(|| format!("Failed to decode batch {}", batches.len() + 1))?;
+        batches.push(batch);
+    }
+    Ok(batches)
+}
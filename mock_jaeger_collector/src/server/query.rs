@@ -0,0 +1,271 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, UNIX_EPOCH};
+
+use actix_web::http::StatusCode;
+use actix_web::web::{Data, Path, Query};
+use actix_web::HttpResponse;
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use super::store::BatchStore;
+use crate::jaeger_models::json::Trace;
+use crate::jaeger_models::{Batch, Process, Span, TagValue};
+use crate::SpanQuery;
+
+/// The number of traces Jaeger returns from a search when no limit is given.
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+/// The envelope Jaeger's query API wraps every response in.
+#[derive(Serialize)]
+struct QueryResponse<T> {
+    data: Option<Vec<T>>,
+    total: usize,
+    limit: usize,
+    offset: usize,
+    errors: Option<Vec<QueryError>>,
+}
+
+#[derive(Serialize)]
+struct QueryError {
+    code: u16,
+    msg: String,
+}
+
+fn data_response<T: Serialize>(data: Vec<T>) -> HttpResponse {
+    HttpResponse::Ok().json(QueryResponse {
+        total: data.len(),
+        data: Some(data),
+        limit: 0,
+        offset: 0,
+        errors: None,
+    })
+}
+
+fn error_response(status: StatusCode, msg: String) -> HttpResponse {
+    HttpResponse::build(status).json(QueryResponse::<()> {
+        data: None,
+        total: 0,
+        limit: 0,
+        offset: 0,
+        errors: Some(vec![QueryError {
+            code: status.as_u16(),
+            msg,
+        }]),
+    })
+}
+
+/// Handle `GET /api/services`, listing the names of every service that has sent spans.
+pub(super) async fn get_services_handler(received_batches: Data<BatchStore>) -> HttpResponse {
+    let services: BTreeSet<String> = {
+        let batches = received_batches.batches();
+        batches
+            .iter()
+            .map(|batch| batch.process.service_name.clone())
+            .collect()
+    };
+    data_response(services.into_iter().collect())
+}
+
+/// Handle `GET /api/services/{service}/operations`, listing the names of every operation
+/// the service has sent spans for.
+pub(super) async fn get_operations_handler(
+    service: Path<String>,
+    received_batches: Data<BatchStore>,
+) -> HttpResponse {
+    let operations: BTreeSet<String> = {
+        let batches = received_batches.batches();
+        batches
+            .iter()
+            .filter(|batch| batch.process.service_name == *service)
+            .flat_map(|batch| batch.spans.iter())
+            .map(|span| span.operation_name.clone())
+            .collect()
+    };
+    data_response(operations.into_iter().collect())
+}
+
+/// Handle `GET /api/traces/{trace_id}`.
+pub(super) async fn get_trace_handler(
+    trace_id: Path<String>,
+    received_batches: Data<BatchStore>,
+) -> HttpResponse {
+    let trace_id = match parse_trace_id(&trace_id) {
+        Ok(trace_id) => trace_id,
+        Err(error) => return error_response(StatusCode::BAD_REQUEST, format!("{:#}", error)),
+    };
+
+    let batches = received_batches.batches();
+    match group_spans_by_trace(&batches).remove(&trace_id) {
+        Some(spans) => data_response(vec![Trace::from_spans(spans)]),
+        None => error_response(StatusCode::NOT_FOUND, "trace not found".into()),
+    }
+}
+
+/// The parameters of a trace search, as sent by the Jaeger UI. Parameters left blank in
+/// the UI are sent empty, so every parameter is accepted as a string.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct SearchParameters {
+    service: Option<String>,
+    operation: Option<String>,
+    tags: Option<String>,
+    limit: Option<String>,
+    start: Option<String>,
+    end: Option<String>,
+    min_duration: Option<String>,
+    max_duration: Option<String>,
+}
+
+/// A parsed trace search. A trace matches when any one of its spans matches the query.
+struct TraceSearch {
+    query: SpanQuery,
+    limit: usize,
+}
+
+impl TraceSearch {
+    fn parse(parameters: SearchParameters) -> Result<Self, anyhow::Error> {
+        fn non_empty(value: Option<String>) -> Option<String> {
+            value.filter(|v| !v.is_empty())
+        }
+
+        let mut query = match non_empty(parameters.service) {
+            Some(service) => SpanQuery::new().service(service),
+            None => bail!("Parameter 'service' is required"),
+        };
+        if let Some(operation) = non_empty(parameters.operation) {
+            query = query.operation(operation);
+        }
+        if let Some(tags) = non_empty(parameters.tags) {
+            let tags: HashMap<String, String> = serde_json::from_str(&tags)
+                .context("Parameter 'tags' must be a JSON object of strings")?;
+            for (key, expected) in tags {
+                query = query.with_tag_matching(key, move |value| {
+                    tag_value_string(value).as_ref() == Some(&expected)
+                });
+            }
+        }
+        if let Some(start) = non_empty(parameters.start) {
+            let start = start
+                .parse()
+                .context("Parameter 'start' must be a number of microseconds")?;
+            query = query.started_after(UNIX_EPOCH + Duration::from_micros(start));
+        }
+        if let Some(end) = non_empty(parameters.end) {
+            let end = end
+                .parse()
+                .context("Parameter 'end' must be a number of microseconds")?;
+            query = query.started_before(UNIX_EPOCH + Duration::from_micros(end));
+        }
+        if let Some(duration) = non_empty(parameters.min_duration) {
+            query = query.min_duration(parse_duration(&duration)?);
+        }
+        if let Some(duration) = non_empty(parameters.max_duration) {
+            query = query.max_duration(parse_duration(&duration)?);
+        }
+        let limit = match non_empty(parameters.limit) {
+            Some(limit) => limit
+                .parse()
+                .context("Parameter 'limit' must be a number")?,
+            None => DEFAULT_SEARCH_LIMIT,
+        };
+
+        Ok(Self { query, limit })
+    }
+}
+
+/// Handle `GET /api/traces`, searching for traces matching the given parameters. The
+/// most recent traces are returned first.
+pub(super) async fn search_traces_handler(
+    parameters: Query<SearchParameters>,
+    received_batches: Data<BatchStore>,
+) -> HttpResponse {
+    let search = match TraceSearch::parse(parameters.into_inner()) {
+        Ok(search) => search,
+        Err(error) => return error_response(StatusCode::BAD_REQUEST, format!("{:#}", error)),
+    };
+
+    let batches = received_batches.batches();
+    let mut traces: Vec<_> = group_spans_by_trace(&batches)
+        .into_values()
+        .filter(|spans| spans.iter().any(|(p, s)| search.query.matches(p, s)))
+        .collect();
+    traces.sort_by_key(|spans| Reverse(spans.iter().map(|(_, s)| s.start_time).max()));
+    traces.truncate(search.limit);
+
+    data_response(traces.into_iter().map(Trace::from_spans).collect())
+}
+
+/// Group every received span by its trace ID, pairing each with the process that sent it.
+fn group_spans_by_trace(batches: &[Batch]) -> HashMap<u128, Vec<(&Process, &Span)>> {
+    let mut traces: HashMap<u128, Vec<(&Process, &Span)>> = HashMap::new();
+    for batch in batches {
+        for span in &batch.spans {
+            let trace_id = (u128::from(span.trace_id_high as u64) << 64)
+                | u128::from(span.trace_id_low as u64);
+            traces
+                .entry(trace_id)
+                .or_default()
+                .push((&batch.process, span));
+        }
+    }
+    traces
+}
+
+fn parse_trace_id(trace_id: &str) -> Result<u128, anyhow::Error> {
+    if trace_id.is_empty()
+        || trace_id.len() > 32
+        || !trace_id.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        bail!("Invalid trace ID: {}", trace_id);
+    }
+    Ok(u128::from_str_radix(trace_id, 16)?)
+}
+
+/// Render a tag's value as the string the Jaeger UI would search for.
+fn tag_value_string(value: &TagValue) -> Option<String> {
+    Some(match value {
+        TagValue::String(value) => value.to_string(),
+        TagValue::Bool(value) => value.to_string(),
+        TagValue::Long(value) => value.to_string(),
+        TagValue::Double(value) => value.to_string(),
+        TagValue::Binary(_) => return None,
+    })
+}
+
+/// Parse a duration in the format used by Go's `time.ParseDuration`, such as `1.5s` or
+/// `1m30s`, which the Jaeger UI uses for its duration filters.
+fn parse_duration(duration: &str) -> Result<Duration, anyhow::Error> {
+    if duration.is_empty() {
+        bail!("Durations must not be empty");
+    }
+
+    let mut micros = 0_f64;
+    let mut remaining = duration;
+    while !remaining.is_empty() {
+        let number_length = remaining
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(remaining.len());
+        let number: f64 = remaining[..number_length]
+            .parse()
+            .with_context(|| format!("Invalid duration: {}", duration))?;
+        remaining = &remaining[number_length..];
+
+        let unit_length = remaining
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(remaining.len());
+        let micros_per_unit = match &remaining[..unit_length] {
+            "ns" => 0.001,
+            "us" | "µs" => 1.0,
+            "ms" => 1_000.0,
+            "s" => 1_000_000.0,
+            "m" => 60_000_000.0,
+            "h" => 3_600_000_000.0,
+            unit => bail!("Unknown unit {:?} in duration {}", unit, duration),
+        };
+        micros += number * micros_per_unit;
+        remaining = &remaining[unit_length..];
+    }
+
+    Ok(Duration::from_micros(micros as u64))
+}
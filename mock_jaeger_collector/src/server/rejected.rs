@@ -0,0 +1,61 @@
+use actix_web::http::{header, StatusCode};
+use actix_web::HttpRequest;
+
+/// The endpoint recorded for datagrams sent to the agent endpoint, which have no path.
+pub(super) const AGENT_ENDPOINT: &str = "agent";
+
+/// The endpoint recorded for OTLP/gRPC exports, which is the path of the `Export` method.
+pub(super) const OTLP_GRPC_ENDPOINT: &str =
+    "/opentelemetry.proto.collector.trace.v1.TraceService/Export";
+
+/// A request, OTLP/gRPC export or agent datagram which the collector refused to store, as
+/// the real Jaeger backend would drop it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RejectedBatch {
+    /// The path the request was sent to, such as `/api/traces`, or `agent` for a
+    /// datagram sent to the agent endpoint. OTLP/gRPC exports are recorded with the path
+    /// of the `Export` method.
+    pub endpoint: String,
+    /// The request's `Content-Type`, if it had one.
+    pub content_type: Option<String>,
+    /// The request's body, after decoding its `Content-Encoding`. This is empty if the
+    /// request was refused before its body was read, for its content type or encoding.
+    /// OTLP/gRPC exports are recorded as their `ExportTraceServiceRequest`, encoded again
+    /// as protobuf.
+    pub payload: Vec<u8>,
+    /// The HTTP status the request was refused with. Agent datagrams are never answered,
+    /// and OTLP/gRPC exports fail with a gRPC status instead, so neither has one.
+    pub status: Option<u16>,
+    /// Why the request was refused.
+    pub error: String,
+}
+
+impl RejectedBatch {
+    pub(super) fn from_request(
+        request: &HttpRequest,
+        payload: &[u8],
+        status: StatusCode,
+        error: String,
+    ) -> Self {
+        Self {
+            endpoint: request.path().to_owned(),
+            content_type: request
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .map(|content_type| String::from_utf8_lossy(content_type.as_bytes()).into_owned()),
+            payload: payload.to_vec(),
+            status: Some(status.as_u16()),
+            error,
+        }
+    }
+
+    pub(super) fn from_grpc_export(payload: Vec<u8>, error: String) -> Self {
+        Self {
+            endpoint: OTLP_GRPC_ENDPOINT.to_owned(),
+            content_type: Some("application/grpc".to_owned()),
+            payload,
+            status: None,
+            error,
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rctree::Node;
+
+use super::rejected::RejectedBatch;
+use super::store::BatchStore;
+use super::wait::{wait_until_trace_matches, WaitForTraceError};
+use crate::jaeger_models::span_tree::build_span_tree;
+use crate::jaeger_models::{Process, SpanWithProcess, TraceForest, TraceGraph};
+use crate::SpanQuery;
+
+/// The resource attribute, or Jaeger process tag, which ties a batch to a session by its
+/// [`CollectorSession::test_id`].
+pub const TEST_ID_ATTRIBUTE: &str = "test.id";
+
+static NEXT_SESSION_NUMBER: AtomicUsize = AtomicUsize::new(1);
+
+/// A test's own view of a shared [`DetachedJaegerCollectorServer`].
+///
+/// A session only sees spans in traces it has registered with
+/// [`CollectorSession::register_trace_id`], and spans in batches whose process has a
+/// [`TEST_ID_ATTRIBUTE`] tag matching its [`CollectorSession::test_id`]. When the
+/// session is dropped, those spans are removed from the collector, so that a long test
+/// run does not accumulate every span it produces.
+///
+/// [`DetachedJaegerCollectorServer`]: crate::DetachedJaegerCollectorServer
+pub struct CollectorSession {
+    test_id: String,
+    trace_ids: Mutex<HashSet<String>>,
+    batch_store: Arc<BatchStore>,
+}
+
+impl CollectorSession {
+    pub(super) fn new(batch_store: Arc<BatchStore>) -> Self {
+        let session_number = NEXT_SESSION_NUMBER.fetch_add(1, Ordering::Relaxed);
+        Self {
+            test_id: format!("session-{}", session_number),
+            trace_ids: Mutex::new(HashSet::new()),
+            batch_store,
+        }
+    }
+
+    /// Get the ID which identifies this session's batches, when set as the value of the
+    /// [`TEST_ID_ATTRIBUTE`] resource attribute.
+    pub fn test_id(&self) -> &str {
+        &self.test_id
+    }
+
+    /// Make the trace with the given hex ID, in either case, visible to this session.
+    pub fn register_trace_id(&self, trace_id: &str) {
+        self.trace_ids
+            .lock()
+            .unwrap()
+            .insert(normalise_trace_id(trace_id));
+    }
+
+    /// Retrieve a trace visible to this session, in the form of a [`rctree::Node<SpanWithProcess>`].
+    pub async fn get_trace(&self, trace_id: &str) -> Result<Node<SpanWithProcess>, anyhow::Error> {
+        self.build_trace(trace_id)
+    }
+
+    /// Find every span visible to this session matching `query`. See
+    /// [`DetachedJaegerCollectorServer::find_spans`].
+    ///
+    /// [`DetachedJaegerCollectorServer::find_spans`]: crate::DetachedJaegerCollectorServer::find_spans
+    pub fn find_spans(&self, query: &SpanQuery) -> Vec<SpanWithProcess> {
+        let trace_ids = self.trace_ids.lock().unwrap();
+        self.batch_store.find_spans(query, |process, span| {
+            self.owns_process(process) || trace_ids.contains(&span.hex_trace_id())
+        })
+    }
+
+    /// Find every trace visible to this session containing a span matching `query`. See
+    /// [`DetachedJaegerCollectorServer::find_traces`].
+    ///
+    /// [`DetachedJaegerCollectorServer::find_traces`]: crate::DetachedJaegerCollectorServer::find_traces
+    pub fn find_traces(&self, query: &SpanQuery) -> Vec<TraceForest> {
+        let trace_ids = self.trace_ids.lock().unwrap();
+        self.batch_store.find_traces(query, |process, span| {
+            self.owns_process(process) || trace_ids.contains(&span.hex_trace_id())
+        })
+    }
+
+    /// Retrieve a trace visible to this session as a [`TraceForest`]. See
+    /// [`DetachedJaegerCollectorServer::get_trace_forest`].
+    ///
+    /// [`DetachedJaegerCollectorServer::get_trace_forest`]: crate::DetachedJaegerCollectorServer::get_trace_forest
+    pub fn get_trace_forest(&self, trace_id: &str) -> TraceForest {
+        TraceForest::new(self.trace_spans(trace_id))
+    }
+
+    /// Retrieve a trace visible to this session as a [`TraceGraph`]. See
+    /// [`DetachedJaegerCollectorServer::get_trace_graph`].
+    ///
+    /// [`DetachedJaegerCollectorServer::get_trace_graph`]: crate::DetachedJaegerCollectorServer::get_trace_graph
+    pub fn get_trace_graph(&self, trace_id: &str) -> TraceGraph {
+        TraceGraph::new(self.trace_spans(trace_id))
+    }
+
+    /// Get every request refused by the collector. Since a refused request could not be
+    /// read, it cannot be tied to a session, so this includes those of every session. See
+    /// [`DetachedJaegerCollectorServer::rejected_batches`].
+    ///
+    /// [`DetachedJaegerCollectorServer::rejected_batches`]: crate::DetachedJaegerCollectorServer::rejected_batches
+    pub fn rejected_batches(&self) -> Vec<RejectedBatch> {
+        self.batch_store.rejected_batches()
+    }
+
+    /// Wait for a trace visible to this session to satisfy `predicate`. See
+    /// [`DetachedJaegerCollectorServer::wait_for_trace`].
+    ///
+    /// [`DetachedJaegerCollectorServer::wait_for_trace`]: crate::DetachedJaegerCollectorServer::wait_for_trace
+    pub async fn wait_for_trace<F>(
+        &self,
+        trace_id: &str,
+        predicate: F,
+        timeout: Duration,
+    ) -> Result<Node<SpanWithProcess>, WaitForTraceError>
+    where
+        F: Fn(&Node<SpanWithProcess>) -> Result<(), anyhow::Error>,
+    {
+        wait_until_trace_matches(
+            &self.batch_store,
+            trace_id,
+            || self.build_trace(trace_id),
+            predicate,
+            timeout,
+        )
+        .await
+    }
+
+    fn build_trace(&self, trace_id: &str) -> Result<Node<SpanWithProcess>, anyhow::Error> {
+        build_span_tree(self.trace_spans(trace_id))
+    }
+
+    fn trace_spans(&self, trace_id: &str) -> Vec<SpanWithProcess> {
+        let trace_id = normalise_trace_id(trace_id);
+        let is_registered = self.trace_ids.lock().unwrap().contains(&trace_id);
+        self.batch_store.trace_spans(&trace_id, |process| {
+            is_registered || self.owns_process(process)
+        })
+    }
+
+    fn owns_process(&self, process: &Process) -> bool {
+        process.tags.iter().flatten().any(|tag| {
+            tag.key == TEST_ID_ATTRIBUTE && tag.v_str.as_deref() == Some(self.test_id.as_str())
+        })
+    }
+}
+
+/// Bring a hex trace ID into the lowercase form of [`Span::hex_trace_id`], so that IDs
+/// registered or looked up in uppercase still match.
+///
+/// [`Span::hex_trace_id`]: crate::jaeger_models::Span::hex_trace_id
+fn normalise_trace_id(trace_id: &str) -> String {
+    trace_id.to_ascii_lowercase()
+}
+
+impl Drop for CollectorSession {
+    fn drop(&mut self) {
+        let trace_ids = self.trace_ids.lock().unwrap();
+        self.batch_store.remove_spans(|process, span| {
+            self.owns_process(process) || trace_ids.contains(&span.hex_trace_id())
+        });
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use tokio::sync::watch;
+
+use super::faults::FaultInjector;
+use super::rejected::RejectedBatch;
+use crate::jaeger_models::{Batch, Process, Span, SpanWithProcess, TraceForest};
+use crate::SpanQuery;
+
+type BatchObserver = Box<dyn Fn(&Batch) + Send>;
+
+/// The in-memory store of every batch received by the server, over any transport.
+/// Subscribers are notified whenever new batches are added, so they need not poll. The
+/// requests refused by the server are kept alongside them, as are the faults injected into
+/// the transports which add batches.
+pub(crate) struct BatchStore {
+    batches: Mutex<Vec<Batch>>,
+    rejected_batches: Mutex<Vec<RejectedBatch>>,
+    faults: FaultInjector,
+    changes: watch::Sender<()>,
+    observers: Mutex<Vec<BatchObserver>>,
+}
+
+impl BatchStore {
+    /// Create a store holding the given batches.
+    pub(crate) fn new(batches: Vec<Batch>) -> Self {
+        Self {
+            batches: Mutex::new(batches),
+            rejected_batches: Mutex::new(Vec::new()),
+            faults: FaultInjector::new(),
+            changes: watch::Sender::new(()),
+            observers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Add the batches received in a single request, or agent datagram, to the store,
+    /// passing each to the observers and then notifying any subscribers. Each batch is
+    /// discarded instead if the injected faults say to drop it.
+    pub(crate) fn add(&self, batches: impl IntoIterator<Item = Batch>) {
+        let batches: Vec<Batch> = batches
+            .into_iter()
+            .filter(|_| !self.faults.should_drop_batch())
+            .collect();
+        for observer in self.observers.lock().unwrap().iter() {
+            batches.iter().for_each(observer);
+        }
+        self.batches.lock().unwrap().extend(batches);
+        self.changes.send_replace(());
+    }
+
+    pub(crate) fn faults(&self) -> &FaultInjector {
+        &self.faults
+    }
+
+    /// Record a request which was refused, rather than stored.
+    pub(crate) fn reject(&self, rejected_batch: RejectedBatch) {
+        self.rejected_batches.lock().unwrap().push(rejected_batch);
+    }
+
+    /// Get every request refused so far, in the order they were received.
+    pub(crate) fn rejected_batches(&self) -> Vec<RejectedBatch> {
+        self.rejected_batches.lock().unwrap().clone()
+    }
+
+    /// Register a function to be called with every batch subsequently added.
+    pub(crate) fn observe(&self, observer: impl Fn(&Batch) + Send + 'static) {
+        self.observers.lock().unwrap().push(Box::new(observer));
+    }
+
+    /// Lock the store for reading.
+    pub(crate) fn batches(&self) -> MutexGuard<'_, Vec<Batch>> {
+        self.batches.lock().unwrap()
+    }
+
+    /// Subscribe to notifications of batches being added. The receiver observes every
+    /// addition made after this call.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<()> {
+        self.changes.subscribe()
+    }
+
+    /// Collect the spans in the given trace from every process accepted by `include`.
+    pub(crate) fn trace_spans(
+        &self,
+        trace_id: &str,
+        include: impl Fn(&Process) -> bool,
+    ) -> Vec<SpanWithProcess> {
+        self.spans_with_processes(|process, span| {
+            span.hex_trace_id() == trace_id && include(process)
+        })
+    }
+
+    /// Collect the spans matching `query`, from among those accepted by `include`.
+    pub(crate) fn find_spans(
+        &self,
+        query: &SpanQuery,
+        include: impl Fn(&Process, &Span) -> bool,
+    ) -> Vec<SpanWithProcess> {
+        self.spans_with_processes(|process, span| {
+            include(process, span) && query.matches(process, span)
+        })
+    }
+
+    /// Collect every trace containing a span matching `query`, from among the spans
+    /// accepted by `include`, ordered by the time the traces started.
+    pub(crate) fn find_traces(
+        &self,
+        query: &SpanQuery,
+        include: impl Fn(&Process, &Span) -> bool,
+    ) -> Vec<TraceForest> {
+        let spans = self.spans_with_processes(include);
+        let matching_trace_ids: HashSet<String> = spans
+            .iter()
+            .filter(|span| query.matches(&span.process, span))
+            .map(|span| span.hex_trace_id())
+            .collect();
+
+        let mut traces: HashMap<String, Vec<SpanWithProcess>> = HashMap::new();
+        for span in spans {
+            let trace_id = span.hex_trace_id();
+            if matching_trace_ids.contains(&trace_id) {
+                traces.entry(trace_id).or_default().push(span);
+            }
+        }
+        let mut traces: Vec<Vec<SpanWithProcess>> = traces.into_values().collect();
+        traces.sort_by_key(|spans| spans.iter().map(|span| span.start_time).min());
+        traces.into_iter().map(TraceForest::new).collect()
+    }
+
+    /// Collect every span accepted by `include`, paired with the process which sent it.
+    fn spans_with_processes(
+        &self,
+        include: impl Fn(&Process, &Span) -> bool,
+    ) -> Vec<SpanWithProcess> {
+        let mut spans = Vec::new();
+        for batch in self.batches().iter() {
+            let mut process = None;
+            for span in batch
+                .spans
+                .iter()
+                .filter(|span| include(&batch.process, span))
+            {
+                let process = process.get_or_insert_with(|| Arc::new(batch.process.clone()));
+                spans.push(SpanWithProcess::new(span.clone(), process.clone()));
+            }
+        }
+        spans
+    }
+
+    /// Remove every span for which `remove` returns true, dropping any batches which are
+    /// left empty.
+    pub(crate) fn remove_spans(&self, remove: impl Fn(&Process, &Span) -> bool) {
+        let mut batches = self.batches();
+        for batch in batches.iter_mut() {
+            let process = &batch.process;
+            batch.spans.retain(|span| !remove(process, span));
+        }
+        batches.retain(|batch| !batch.spans.is_empty());
+    }
+}
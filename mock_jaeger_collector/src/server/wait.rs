@@ -0,0 +1,103 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use rctree::Node;
+use tokio::time::{timeout_at, Instant};
+
+use super::store::BatchStore;
+use crate::jaeger_models::{SpanWithProcess, TraceForest};
+use crate::rendering::RenderTree;
+
+/// The error returned by [`DetachedJaegerCollectorServer::wait_for_trace`] when no version
+/// of the trace satisfied the predicate before the timeout elapsed.
+///
+/// The error is `Send` and `Sync`, so it can be propagated with `?` into an
+/// [`anyhow::Error`], keeping the spans of the trace last seen rather than their tree.
+///
+/// [`DetachedJaegerCollectorServer::wait_for_trace`]: crate::DetachedJaegerCollectorServer::wait_for_trace
+#[derive(Debug)]
+pub struct WaitForTraceError {
+    trace_id: String,
+    timeout: Duration,
+    last_seen: Option<Vec<SpanWithProcess>>,
+    last_error: anyhow::Error,
+}
+
+impl WaitForTraceError {
+    /// The most recent version of the trace which could be assembled, if any was,
+    /// assembled again from its spans.
+    pub fn last_seen(&self) -> Option<Node<SpanWithProcess>> {
+        // The spans were taken from a single tree, so always form one again
+        let spans = self.last_seen.clone()?;
+        TraceForest::new(spans).roots().first().cloned()
+    }
+
+    /// Why the most recent version of the trace was rejected, either by the predicate,
+    /// or because it could not be assembled.
+    pub fn last_error(&self) -> &anyhow::Error {
+        &self.last_error
+    }
+}
+
+impl Display for WaitForTraceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Trace {} was not as expected within {:?}: {:#}",
+            self.trace_id, self.timeout, self.last_error
+        )?;
+        if let Some(trace) = self.last_seen() {
+            write!(f, "\nLast seen trace:\n{}", trace.render_tree())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for WaitForTraceError {}
+
+/// Wait until `build_trace` assembles a trace satisfying `predicate`, assembling it again
+/// each time batches are added to the store, until `timeout` elapses.
+pub(super) async fn wait_until_trace_matches<B, F>(
+    batch_store: &BatchStore,
+    trace_id: &str,
+    build_trace: B,
+    predicate: F,
+    timeout: Duration,
+) -> Result<Node<SpanWithProcess>, WaitForTraceError>
+where
+    B: Fn() -> Result<Node<SpanWithProcess>, anyhow::Error>,
+    F: Fn(&Node<SpanWithProcess>) -> Result<(), anyhow::Error>,
+{
+    let deadline = Instant::now() + timeout;
+    let mut changes = batch_store.subscribe();
+    let mut last_seen = None;
+    loop {
+        // Mark the store as seen before reading it, so that batches added while
+        // we check the predicate will wake us up again.
+        changes.borrow_and_update();
+        let last_error = match build_trace() {
+            Ok(trace) => match predicate(&trace) {
+                Ok(()) => return Ok(trace),
+                Err(error) => {
+                    last_seen = Some(
+                        trace
+                            .descendants()
+                            .map(|node| node.borrow().clone())
+                            .collect(),
+                    );
+                    error
+                }
+            },
+            Err(error) => error,
+        };
+
+        if timeout_at(deadline, changes.changed()).await.is_err() {
+            return Err(WaitForTraceError {
+                trace_id: trace_id.to_owned(),
+                timeout,
+                last_seen,
+                last_error,
+            });
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use super::{id_from_hex, trace_id_from_hex, Annotation, Endpoint, ZipkinKind, ZipkinSpan};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonSpan {
+    trace_id: String,
+    id: String,
+    parent_id: Option<String>,
+    name: Option<String>,
+    kind: Option<JsonKind>,
+    timestamp: Option<i64>,
+    duration: Option<i64>,
+    local_endpoint: Option<JsonEndpoint>,
+    remote_endpoint: Option<JsonEndpoint>,
+    #[serde(default)]
+    annotations: Vec<JsonAnnotation>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+    #[serde(default)]
+    debug: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum JsonKind {
+    Client,
+    Server,
+    Producer,
+    Consumer,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonEndpoint {
+    service_name: Option<String>,
+    ipv4: Option<String>,
+    ipv6: Option<String>,
+    port: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct JsonAnnotation {
+    timestamp: i64,
+    value: String,
+}
+
+/// Decode a JSON list of spans in Zipkin's v2 model.
+pub(super) fn read_spans(bytes: &[u8]) -> Result<Vec<ZipkinSpan>, anyhow::Error> {
+    let spans: Vec<JsonSpan> =
+        serde_json::from_slice(bytes).context("Failed to decode JSON spans")?;
+    spans.into_iter().map(ZipkinSpan::try_from).collect()
+}
+
+impl TryFrom<JsonSpan> for ZipkinSpan {
+    type Error = anyhow::Error;
+
+    fn try_from(span: JsonSpan) -> Result<Self, Self::Error> {
+        let (trace_id_high, trace_id_low) = trace_id_from_hex(&span.trace_id)?;
+        Ok(Self {
+            trace_id_high,
+            trace_id_low,
+            id: id_from_hex(&span.id)?,
+            parent_id: span.parent_id.as_deref().map(id_from_hex).transpose()?,
+            name: span.name,
+            kind: span.kind.map(|kind| match kind {
+                JsonKind::Client => ZipkinKind::Client,
+                JsonKind::Server => ZipkinKind::Server,
+                JsonKind::Producer => ZipkinKind::Producer,
+                JsonKind::Consumer => ZipkinKind::Consumer,
+            }),
+            timestamp: span.timestamp,
+            duration: span.duration,
+            local_endpoint: span.local_endpoint.map(Endpoint::from),
+            remote_endpoint: span.remote_endpoint.map(Endpoint::from),
+            annotations: span
+                .annotations
+                .into_iter()
+                .map(|a| Annotation {
+                    timestamp: a.timestamp,
+                    value: a.value,
+                })
+                .collect(),
+            tags: span.tags,
+            debug: span.debug,
+        })
+    }
+}
+
+impl From<JsonEndpoint> for Endpoint {
+    fn from(endpoint: JsonEndpoint) -> Self {
+        Self {
+            service_name: endpoint.service_name,
+            ipv4: endpoint.ipv4,
+            ipv6: endpoint.ipv6,
+            port: endpoint.port,
+        }
+    }
+}
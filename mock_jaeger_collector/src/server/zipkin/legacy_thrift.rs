@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use anyhow::Context;
+use thrift::protocol::{field_id, TBinaryInputProtocol, TInputProtocol, TType};
+
+use super::{Annotation, Endpoint, ZipkinKind, ZipkinSpan};
+
+// The structs below mirror those in Zipkin's `zipkinCore.thrift`, keeping only the
+// fields needed to translate them into the v2 model.
+
+#[derive(Default)]
+struct ThriftSpan {
+    trace_id: i64,
+    trace_id_high: i64,
+    name: String,
+    id: i64,
+    parent_id: Option<i64>,
+    annotations: Vec<ThriftAnnotation>,
+    binary_annotations: Vec<ThriftBinaryAnnotation>,
+    debug: bool,
+    timestamp: Option<i64>,
+    duration: Option<i64>,
+}
+
+#[derive(Default)]
+struct ThriftAnnotation {
+    timestamp: i64,
+    value: String,
+    host: Option<Endpoint>,
+}
+
+#[derive(Default)]
+struct ThriftBinaryAnnotation {
+    key: String,
+    value: Vec<u8>,
+    annotation_type: i32,
+    host: Option<Endpoint>,
+}
+
+/// The `AnnotationType`s a binary annotation's value may be encoded as.
+mod annotation_type {
+    pub const BOOL: i32 = 0;
+    pub const I16: i32 = 2;
+    pub const I32: i32 = 3;
+    pub const I64: i32 = 4;
+    pub const DOUBLE: i32 = 5;
+}
+
+/// Decode a binary Thrift list of spans in Zipkin's v1 model, translating them into
+/// the v2 model.
+pub(super) fn read_spans(bytes: &[u8]) -> Result<Vec<ZipkinSpan>, anyhow::Error> {
+    let mut binary_input = TBinaryInputProtocol::new(bytes, true);
+    let list_ident = binary_input.read_list_begin()?;
+    let spans = (0..list_ident.size)
+        .map(|_| read_span(&mut binary_input).map(ZipkinSpan::from))
+        .collect::<Result<_, _>>()
+        .context("Failed to decode Thrift spans")?;
+    binary_input.read_list_end()?;
+    Ok(spans)
+}
+
+/// Read the fields of a struct, passing each to `read_field` along with its ID.
+fn read_struct<T: Default>(
+    input: &mut dyn TInputProtocol,
+    mut read_field: impl FnMut(&mut dyn TInputProtocol, &mut T, i16) -> thrift::Result<bool>,
+) -> thrift::Result<T> {
+    let mut value = T::default();
+    input.read_struct_begin()?;
+    loop {
+        let field_ident = input.read_field_begin()?;
+        if field_ident.field_type == TType::Stop {
+            break;
+        }
+        if !read_field(input, &mut value, field_id(&field_ident)?)? {
+            input.skip(field_ident.field_type)?;
+        }
+        input.read_field_end()?;
+    }
+    input.read_struct_end()?;
+    Ok(value)
+}
+
+fn read_list<T>(
+    input: &mut dyn TInputProtocol,
+    read_element: fn(&mut dyn TInputProtocol) -> thrift::Result<T>,
+) -> thrift::Result<Vec<T>> {
+    let list_ident = input.read_list_begin()?;
+    let elements = (0..list_ident.size)
+        .map(|_| read_element(input))
+        .collect::<Result<_, _>>()?;
+    input.read_list_end()?;
+    Ok(elements)
+}
+
+fn read_span(input: &mut dyn TInputProtocol) -> thrift::Result<ThriftSpan> {
+    read_struct(input, |input, span: &mut ThriftSpan, id| {
+        match id {
+            1 => span.trace_id = input.read_i64()?,
+            3 => span.name = input.read_string()?,
+            4 => span.id = input.read_i64()?,
+            5 => span.parent_id = Some(input.read_i64()?),
+            6 => span.annotations = read_list(input, read_annotation)?,
+            8 => span.binary_annotations = read_list(input, read_binary_annotation)?,
+            9 => span.debug = input.read_bool()?,
+            10 => span.timestamp = Some(input.read_i64()?),
+            11 => span.duration = Some(input.read_i64()?),
+            12 => span.trace_id_high = input.read_i64()?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })
+}
+
+fn read_annotation(input: &mut dyn TInputProtocol) -> thrift::Result<ThriftAnnotation> {
+    read_struct(input, |input, annotation: &mut ThriftAnnotation, id| {
+        match id {
+            1 => annotation.timestamp = input.read_i64()?,
+            2 => annotation.value = input.read_string()?,
+            3 => annotation.host = Some(read_endpoint(input)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })
+}
+
+fn read_binary_annotation(
+    input: &mut dyn TInputProtocol,
+) -> thrift::Result<ThriftBinaryAnnotation> {
+    read_struct(
+        input,
+        |input, annotation: &mut ThriftBinaryAnnotation, id| {
+            match id {
+                1 => annotation.key = input.read_string()?,
+                2 => annotation.value = input.read_bytes()?,
+                3 => annotation.annotation_type = input.read_i32()?,
+                4 => annotation.host = Some(read_endpoint(input)?),
+                _ => return Ok(false),
+            }
+            Ok(true)
+        },
+    )
+}
+
+fn read_endpoint(input: &mut dyn TInputProtocol) -> thrift::Result<Endpoint> {
+    read_struct(input, |input, endpoint: &mut Endpoint, id| {
+        match id {
+            1 => {
+                let ipv4 = input.read_i32()?;
+                if ipv4 != 0 {
+                    endpoint.ipv4 = Some(Ipv4Addr::from(ipv4 as u32).to_string());
+                }
+            }
+            2 => {
+                let port = input.read_i16()? as u16;
+                if port != 0 {
+                    endpoint.port = Some(i32::from(port));
+                }
+            }
+            3 => {
+                let service_name = input.read_string()?;
+                if !service_name.is_empty() {
+                    endpoint.service_name = Some(service_name);
+                }
+            }
+            4 => {
+                let ipv6: Option<[u8; 16]> = input.read_bytes()?.as_slice().try_into().ok();
+                endpoint.ipv6 = ipv6.map(|ip| Ipv6Addr::from(ip).to_string());
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })
+}
+
+impl From<ThriftSpan> for ZipkinSpan {
+    /// Translate a v1 span into the v2 model, deriving its kind and endpoints from the
+    /// "core" annotations, as Zipkin does. Spans shared between a client and a server
+    /// are attributed to whichever side's annotation appears first.
+    fn from(span: ThriftSpan) -> Self {
+        let mut kind = None;
+        let mut local_endpoint = None;
+        let mut annotations = Vec::new();
+        for annotation in &span.annotations {
+            let annotation_kind = match annotation.value.as_str() {
+                "cs" | "cr" => Some(ZipkinKind::Client),
+                "sr" | "ss" => Some(ZipkinKind::Server),
+                "ms" => Some(ZipkinKind::Producer),
+                "mr" => Some(ZipkinKind::Consumer),
+                _ => None,
+            };
+            match annotation_kind {
+                Some(annotation_kind) if kind.is_none() => {
+                    kind = Some(annotation_kind);
+                    local_endpoint = annotation.host.clone();
+                }
+                Some(_) => (),
+                None => annotations.push(Annotation {
+                    timestamp: annotation.timestamp,
+                    value: annotation.value.clone(),
+                }),
+            }
+        }
+
+        let mut remote_endpoint = None;
+        let mut tags = BTreeMap::new();
+        for annotation in span.binary_annotations {
+            match annotation.key.as_str() {
+                // Address annotations describe the other side of a remote call.
+                "ca" | "sa" | "ma" => remote_endpoint = annotation.host,
+                _ => {
+                    if local_endpoint.is_none() {
+                        local_endpoint = annotation.host.clone();
+                    }
+                    tags.insert(
+                        annotation.key,
+                        binary_annotation_value(&annotation.value, annotation.annotation_type),
+                    );
+                }
+            }
+        }
+        if local_endpoint.is_none() {
+            local_endpoint = span.annotations.iter().find_map(|a| a.host.clone());
+        }
+
+        let first_timestamp = span.annotations.iter().map(|a| a.timestamp).min();
+        let last_timestamp = span.annotations.iter().map(|a| a.timestamp).max();
+        let timestamp = span.timestamp.or(first_timestamp);
+        let duration = span.duration.or_else(|| {
+            first_timestamp
+                .zip(last_timestamp)
+                .map(|(first, last)| last - first)
+                .filter(|d| *d != 0)
+        });
+
+        Self {
+            trace_id_high: span.trace_id_high,
+            trace_id_low: span.trace_id,
+            id: span.id,
+            parent_id: span.parent_id.filter(|id| *id != 0),
+            name: Some(span.name).filter(|name| !name.is_empty()),
+            kind,
+            timestamp,
+            duration,
+            local_endpoint,
+            remote_endpoint,
+            annotations,
+            tags,
+            debug: span.debug,
+        }
+    }
+}
+
+/// Render a binary annotation's big-endian encoded value as a string, as Zipkin's v2
+/// model only has string tags.
+fn binary_annotation_value(value: &[u8], annotation_type: i32) -> String {
+    let rendered = match annotation_type {
+        annotation_type::BOOL => value.first().map(|b| (*b != 0).to_string()),
+        annotation_type::I16 => value
+            .try_into()
+            .ok()
+            .map(|b| i16::from_be_bytes(b).to_string()),
+        annotation_type::I32 => value
+            .try_into()
+            .ok()
+            .map(|b| i32::from_be_bytes(b).to_string()),
+        annotation_type::I64 => value
+            .try_into()
+            .ok()
+            .map(|b| i64::from_be_bytes(b).to_string()),
+        annotation_type::DOUBLE => value
+            .try_into()
+            .ok()
+            .map(|b| f64::from_be_bytes(b).to_string()),
+        _ => None,
+    };
+    rendered.unwrap_or_else(|| String::from_utf8_lossy(value).into_owned())
+}
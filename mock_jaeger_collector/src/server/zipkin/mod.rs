@@ -0,0 +1,263 @@
+mod json;
+mod legacy_thrift;
+mod proto;
+
+use std::collections::BTreeMap;
+
+use actix_web::http::StatusCode;
+use actix_web::web::{Data, Payload};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use anyhow::{anyhow, bail};
+
+use super::rejected::RejectedBatch;
+use super::store::BatchStore;
+use super::{content_encoding_is_supported, inject_faults, read_payload};
+use crate::jaeger_models::{Batch, Log, Process, Span, Tag, TagType};
+
+/// The service name Zipkin assigns to spans whose local endpoint has none.
+const UNKNOWN_SERVICE_NAME: &str = "unknown";
+
+/// A span in Zipkin's v2 model, which each of the wire formats is decoded into before
+/// being translated into the Jaeger model.
+struct ZipkinSpan {
+    trace_id_high: i64,
+    trace_id_low: i64,
+    id: i64,
+    parent_id: Option<i64>,
+    name: Option<String>,
+    kind: Option<ZipkinKind>,
+    timestamp: Option<i64>,
+    duration: Option<i64>,
+    local_endpoint: Option<Endpoint>,
+    remote_endpoint: Option<Endpoint>,
+    annotations: Vec<Annotation>,
+    tags: BTreeMap<String, String>,
+    debug: bool,
+}
+
+#[derive(Clone, Copy)]
+enum ZipkinKind {
+    Client,
+    Server,
+    Producer,
+    Consumer,
+}
+
+impl ZipkinKind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Client => "client",
+            Self::Server => "server",
+            Self::Producer => "producer",
+            Self::Consumer => "consumer",
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Endpoint {
+    service_name: Option<String>,
+    ipv4: Option<String>,
+    ipv6: Option<String>,
+    port: Option<i32>,
+}
+
+struct Annotation {
+    timestamp: i64,
+    value: String,
+}
+
+/// Handle `POST /api/v2/spans`, which accepts a list of spans encoded as either JSON or
+/// protobuf. As with Zipkin itself, requests without a `Content-Type` are treated as JSON.
+pub(super) async fn post_zipkin_v2_spans_handler(
+    request: HttpRequest,
+    payload: Payload,
+    received_batches: Data<BatchStore>,
+) -> HttpResponse {
+    if let Some(response) = inject_faults(&received_batches).await {
+        return response;
+    }
+    let decode = match request.mime_type() {
+        Ok(None) => json::read_spans,
+        Ok(Some(mime)) if mime.essence_str() == "application/json" => json::read_spans,
+        Ok(Some(mime)) if mime.essence_str() == "application/x-protobuf" => proto::read_spans,
+        _ => return reject_content_type(&request, &received_batches),
+    };
+
+    store_spans(request, payload, received_batches, decode).await
+}
+
+/// Handle `POST /api/v1/spans`, which accepts a list of spans in the legacy Zipkin
+/// Thrift model.
+pub(super) async fn post_zipkin_v1_spans_handler(
+    request: HttpRequest,
+    payload: Payload,
+    received_batches: Data<BatchStore>,
+) -> HttpResponse {
+    if let Some(response) = inject_faults(&received_batches).await {
+        return response;
+    }
+    match request.mime_type() {
+        Ok(Some(mime)) if mime.essence_str() == "application/x-thrift" => (),
+        _ => return reject_content_type(&request, &received_batches),
+    }
+
+    store_spans(
+        request,
+        payload,
+        received_batches,
+        legacy_thrift::read_spans,
+    )
+    .await
+}
+
+fn reject_content_type(request: &HttpRequest, received_batches: &BatchStore) -> HttpResponse {
+    let status = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+    let error = "Unsupported content type".to_owned();
+    received_batches.reject(RejectedBatch::from_request(request, &[], status, error));
+    HttpResponse::build(status).finish()
+}
+
+async fn store_spans(
+    request: HttpRequest,
+    payload: Payload,
+    received_batches: Data<BatchStore>,
+    decode: fn(&[u8]) -> Result<Vec<ZipkinSpan>, anyhow::Error>,
+) -> HttpResponse {
+    let reject = |payload: &[u8], status: StatusCode, error: String| {
+        received_batches.reject(RejectedBatch::from_request(
+            &request,
+            payload,
+            status,
+            error.clone(),
+        ));
+        HttpResponse::build(status).body(error)
+    };
+
+    if !content_encoding_is_supported(&request) {
+        let error = "Unsupported content encoding".to_owned();
+        return reject(&[], StatusCode::UNSUPPORTED_MEDIA_TYPE, error);
+    }
+    let bytes = match read_payload(&request, payload).await {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            let error = format!("Unable to read request body: {:#}", error);
+            return reject(&[], StatusCode::INTERNAL_SERVER_ERROR, error);
+        }
+    };
+
+    match decode(&bytes) {
+        Ok(spans) => {
+            received_batches.add(batches_from_zipkin(spans));
+            HttpResponse::Accepted().finish()
+        }
+        Err(error) => reject(&bytes, StatusCode::BAD_REQUEST, format!("{:#}", error)),
+    }
+}
+
+/// Translate Zipkin spans into Jaeger batches, grouping spans which share a local
+/// endpoint into the same batch.
+fn batches_from_zipkin(spans: Vec<ZipkinSpan>) -> Vec<Batch> {
+    let mut batches: Vec<Batch> = Vec::new();
+    for span in spans {
+        let process = process_from_endpoint(span.local_endpoint.clone());
+        let span = span_from_zipkin(span);
+        match batches.iter_mut().find(|b| b.process == process) {
+            Some(batch) => batch.spans.push(span),
+            None => batches.push(Batch::new(process, vec![span], None, None)),
+        }
+    }
+    batches
+}
+
+fn process_from_endpoint(endpoint: Option<Endpoint>) -> Process {
+    let endpoint = endpoint.unwrap_or_default();
+    let tags = endpoint.ipv4.map(|ip| vec![Tag::string("ip", ip)]);
+    Process::new(
+        endpoint
+            .service_name
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| UNKNOWN_SERVICE_NAME.to_owned()),
+        tags,
+    )
+}
+
+fn span_from_zipkin(span: ZipkinSpan) -> Span {
+    let mut tags: Vec<Tag> = span
+        .tags
+        .into_iter()
+        .map(|(key, value)| Tag::string(key, value))
+        .collect();
+    if let Some(kind) = span.kind {
+        tags.push(Tag::string("span.kind", kind.name()));
+    }
+    if let Some(remote_endpoint) = span.remote_endpoint {
+        if let Some(service_name) = remote_endpoint.service_name {
+            tags.push(Tag::string("peer.service", service_name));
+        }
+        if let Some(ipv4) = remote_endpoint.ipv4 {
+            tags.push(Tag::string("peer.ipv4", ipv4));
+        }
+        if let Some(ipv6) = remote_endpoint.ipv6 {
+            tags.push(Tag::string("peer.ipv6", ipv6));
+        }
+        if let Some(port) = remote_endpoint.port {
+            tags.push(Tag::new(
+                "peer.port".into(),
+                TagType::LONG,
+                None,
+                None,
+                None,
+                i64::from(port),
+                None,
+            ));
+        }
+    }
+
+    let logs: Vec<Log> = span
+        .annotations
+        .into_iter()
+        .map(|a| Log::new(a.timestamp, vec![Tag::string("event", a.value)]))
+        .collect();
+
+    Span::new(
+        span.trace_id_low,
+        span.trace_id_high,
+        span.id,
+        span.parent_id.unwrap_or(0),
+        span.name.unwrap_or_default(),
+        None,
+        // Zipkin only reports sampled spans, so the sampled bit is always set.
+        if span.debug { 3 } else { 1 },
+        span.timestamp.unwrap_or(0),
+        span.duration.unwrap_or(0),
+        if tags.is_empty() { None } else { Some(tags) },
+        if logs.is_empty() { None } else { Some(logs) },
+    )
+}
+
+/// Parse a 16 or 32 character hex trace ID into its high and low halves.
+fn trace_id_from_hex(hex: &str) -> Result<(i64, i64), anyhow::Error> {
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bail!("Trace ID {} is not valid hex", hex);
+    }
+    match hex.len() {
+        16 => Ok((0, id_from_hex(hex)?)),
+        32 => Ok((id_from_hex(&hex[..16])?, id_from_hex(&hex[16..])?)),
+        _ => bail!("Trace ID {} must be 16 or 32 hex characters", hex),
+    }
+}
+
+/// Parse a hex span ID of up to 16 characters.
+fn id_from_hex(hex: &str) -> Result<i64, anyhow::Error> {
+    if hex.is_empty() || hex.len() > 16 {
+        bail!("ID {} must be between 1 and 16 hex characters", hex);
+    }
+    // `from_str_radix` would also accept a leading `+`, which Zipkin does not
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bail!("ID {} is not valid hex", hex);
+    }
+    u64::from_str_radix(hex, 16)
+        .map(|id| id as i64)
+        .map_err(|_| anyhow!("ID {} is not valid hex", hex))
+}
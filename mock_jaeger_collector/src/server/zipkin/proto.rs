@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use anyhow::{anyhow, Context};
+use prost::{Enumeration, Message};
+
+use super::{Annotation, Endpoint, ZipkinKind, ZipkinSpan};
+
+// The messages below mirror those in Zipkin's `zipkin.proto3`.
+
+#[derive(Clone, PartialEq, Message)]
+struct ListOfSpans {
+    #[prost(message, repeated, tag = "1")]
+    spans: Vec<ProtoSpan>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoSpan {
+    #[prost(bytes = "vec", tag = "1")]
+    trace_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    parent_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    id: Vec<u8>,
+    #[prost(enumeration = "ProtoKind", tag = "4")]
+    kind: i32,
+    #[prost(string, tag = "5")]
+    name: String,
+    #[prost(fixed64, tag = "6")]
+    timestamp: u64,
+    #[prost(uint64, tag = "7")]
+    duration: u64,
+    #[prost(message, optional, tag = "8")]
+    local_endpoint: Option<ProtoEndpoint>,
+    #[prost(message, optional, tag = "9")]
+    remote_endpoint: Option<ProtoEndpoint>,
+    #[prost(message, repeated, tag = "10")]
+    annotations: Vec<ProtoAnnotation>,
+    #[prost(map = "string, string", tag = "11")]
+    tags: HashMap<String, String>,
+    #[prost(bool, tag = "12")]
+    debug: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration)]
+#[repr(i32)]
+enum ProtoKind {
+    SpanKindUnspecified = 0,
+    Client = 1,
+    Server = 2,
+    Producer = 3,
+    Consumer = 4,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoEndpoint {
+    #[prost(string, tag = "1")]
+    service_name: String,
+    #[prost(bytes = "vec", tag = "2")]
+    ipv4: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    ipv6: Vec<u8>,
+    #[prost(int32, tag = "4")]
+    port: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoAnnotation {
+    #[prost(fixed64, tag = "1")]
+    timestamp: u64,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+/// Decode a protobuf `ListOfSpans` in Zipkin's v2 model.
+pub(super) fn read_spans(bytes: &[u8]) -> Result<Vec<ZipkinSpan>, anyhow::Error> {
+    let list = ListOfSpans::decode(bytes).context("Failed to decode protobuf spans")?;
+    list.spans.into_iter().map(ZipkinSpan::try_from).collect()
+}
+
+impl TryFrom<ProtoSpan> for ZipkinSpan {
+    type Error = anyhow::Error;
+
+    fn try_from(span: ProtoSpan) -> Result<Self, Self::Error> {
+        let (trace_id_high, trace_id_low) = match span.trace_id.len() {
+            8 => (0, id_from_bytes(&span.trace_id)?),
+            16 => (
+                id_from_bytes(&span.trace_id[..8])?,
+                id_from_bytes(&span.trace_id[8..])?,
+            ),
+            length => return Err(anyhow!("Trace ID must be 8 or 16 bytes, not {}", length)),
+        };
+        let parent_id = if span.parent_id.is_empty() {
+            None
+        } else {
+            Some(id_from_bytes(&span.parent_id)?)
+        };
+
+        Ok(Self {
+            trace_id_high,
+            trace_id_low,
+            id: id_from_bytes(&span.id)?,
+            parent_id,
+            name: Some(span.name).filter(|name| !name.is_empty()),
+            kind: match ProtoKind::try_from(span.kind) {
+                Ok(ProtoKind::Client) => Some(ZipkinKind::Client),
+                Ok(ProtoKind::Server) => Some(ZipkinKind::Server),
+                Ok(ProtoKind::Producer) => Some(ZipkinKind::Producer),
+                Ok(ProtoKind::Consumer) => Some(ZipkinKind::Consumer),
+                _ => None,
+            },
+            timestamp: Some(span.timestamp as i64).filter(|t| *t != 0),
+            duration: Some(span.duration as i64).filter(|d| *d != 0),
+            local_endpoint: span.local_endpoint.map(Endpoint::from),
+            remote_endpoint: span.remote_endpoint.map(Endpoint::from),
+            annotations: span
+                .annotations
+                .into_iter()
+                .map(|a| Annotation {
+                    timestamp: a.timestamp as i64,
+                    value: a.value,
+                })
+                .collect(),
+            tags: span.tags.into_iter().collect(),
+            debug: span.debug,
+        })
+    }
+}
+
+impl From<ProtoEndpoint> for Endpoint {
+    fn from(endpoint: ProtoEndpoint) -> Self {
+        let ipv4: Option<[u8; 4]> = endpoint.ipv4.as_slice().try_into().ok();
+        let ipv6: Option<[u8; 16]> = endpoint.ipv6.as_slice().try_into().ok();
+        Self {
+            service_name: Some(endpoint.service_name).filter(|name| !name.is_empty()),
+            ipv4: ipv4.map(|ip| Ipv4Addr::from(ip).to_string()),
+            ipv6: ipv6.map(|ip| Ipv6Addr::from(ip).to_string()),
+            port: Some(endpoint.port).filter(|port| *port != 0),
+        }
+    }
+}
+
+fn id_from_bytes(bytes: &[u8]) -> Result<i64, anyhow::Error> {
+    let bytes: [u8; 8] = bytes
+        .try_into()
+        .map_err(|_| anyhow!("ID must be 8 bytes, not {}", bytes.len()))?;
+    Ok(i64::from_be_bytes(bytes))
+}
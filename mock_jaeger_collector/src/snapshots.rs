@@ -0,0 +1,294 @@
+//! Golden-file snapshots of the shape of a trace.
+//!
+//! A snapshot records each span's operation name, its nesting, the keys of its tags and
+//! the values of selected tags. Sibling spans, and each span's tags, are sorted, so that
+//! spans which were exported concurrently do not make the snapshot unstable. Tag values
+//! are passed through redactions, which by default replace UUIDs, hex span and trace IDs,
+//! and the port numbers of addresses and of tags such as `net.peer.port`. Timestamps and
+//! durations are never part of a snapshot.
+//!
+//! Snapshots are compared against a file, and a missing or mismatched file is an error.
+//! Set the [`UPDATE_SNAPSHOTS_VARIABLE`] environment variable to `1` to write the snapshots
+//! instead.
+
+use std::collections::HashSet;
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use rctree::Node;
+use regex::{Captures, Regex};
+
+use crate::jaeger_models::{Span, SpanWithProcess, TagValue};
+
+/// The environment variable which, when set to `1` or `true`, makes
+/// [`TraceSnapshot::assert_matches`] write snapshots rather than compare against them.
+pub const UPDATE_SNAPSHOTS_VARIABLE: &str = "UPDATE_TRACE_SNAPSHOTS";
+
+enum Redaction {
+    Tag(String),
+    /// Replace the value of tags whose key is the given name, or ends with it as its last
+    /// dot-separated segment, such as `net.peer.port` for `port`.
+    TagKeySegment(String, String),
+    Pattern(Regex, String),
+    /// Replace the matches of a pattern for hex span and trace IDs which contain at least
+    /// one letter, so that decimal numbers of the same length are left alone.
+    HexId(Regex),
+}
+
+/// Describes how a trace is rendered as a snapshot.
+pub struct TraceSnapshot {
+    tag_values: HashSet<String>,
+    redactions: Vec<Redaction>,
+}
+
+impl Default for TraceSnapshot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceSnapshot {
+    /// Render tag keys only, with the default redactions.
+    pub fn new() -> Self {
+        let default_redactions = [
+            (
+                r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b",
+                "[uuid]",
+            ),
+            // Only ports following a host, so that times such as `12:30` are left alone
+            (
+                r"(?P<host>\blocalhost|\b\d{1,3}(?:\.\d{1,3}){3}|\]):\d{2,5}\b",
+                "${host}:[port]",
+            ),
+        ];
+        let mut redactions: Vec<Redaction> = default_redactions
+            .iter()
+            .map(|(pattern, replacement)| {
+                Redaction::Pattern(Regex::new(pattern).unwrap(), replacement.to_string())
+            })
+            .collect();
+        redactions.push(Redaction::HexId(
+            Regex::new(r"\b[0-9a-f]{32}\b|\b[0-9a-f]{16}\b").unwrap(),
+        ));
+        redactions.push(Redaction::TagKeySegment("port".into(), "[port]".into()));
+        Self {
+            tag_values: HashSet::new(),
+            redactions,
+        }
+    }
+
+    /// Also render the values of the tags with the given keys.
+    pub fn with_tag_values<K: Into<String>>(mut self, keys: impl IntoIterator<Item = K>) -> Self {
+        self.tag_values.extend(keys.into_iter().map(Into::into));
+        self
+    }
+
+    /// Replace the whole value of the tag with the given key.
+    pub fn redact_tag(mut self, key: impl Into<String>) -> Self {
+        self.redactions.push(Redaction::Tag(key.into()));
+        self
+    }
+
+    /// Replace every match of `pattern` in tag values with `replacement`, which may refer
+    /// to capture groups as with [`Regex::replace_all`].
+    pub fn redact_pattern(mut self, pattern: Regex, replacement: impl Into<String>) -> Self {
+        self.redactions
+            .push(Redaction::Pattern(pattern, replacement.into()));
+        self
+    }
+
+    /// Remove all redactions, including the defaults.
+    pub fn without_redactions(mut self) -> Self {
+        self.redactions.clear();
+        self
+    }
+
+    /// Render the trace as a snapshot.
+    pub fn render(&self, trace: &Node<SpanWithProcess>) -> String {
+        let mut snapshot = self.render_subtree(trace, 0);
+        snapshot.push('\n');
+        snapshot
+    }
+
+    fn render_subtree(&self, span: &Node<SpanWithProcess>, depth: usize) -> String {
+        let mut lines = vec![format!(
+            "{:indent$}{}{}",
+            "",
+            span.borrow().operation_name,
+            self.render_tags(&span.borrow()),
+            indent = depth * 2
+        )];
+        let mut children: Vec<String> = span
+            .children()
+            .map(|child| self.render_subtree(&child, depth + 1))
+            .collect();
+        children.sort();
+        lines.extend(children);
+        lines.join("\n")
+    }
+
+    fn render_tags(&self, span: &Span) -> String {
+        let mut tags: Vec<String> = span
+            .tags
+            .iter()
+            .flatten()
+            .map(|tag| match tag.value() {
+                Ok(value) if self.tag_values.contains(&tag.key) => {
+                    format!("{}={}", tag.key, self.redact(&tag.key, &value))
+                }
+                _ => tag.key.clone(),
+            })
+            .collect();
+        if tags.is_empty() {
+            return String::new();
+        }
+        tags.sort();
+        format!(" {{{}}}", tags.join(", "))
+    }
+
+    fn redact(&self, key: &str, value: &TagValue) -> String {
+        let mut rendered = match value {
+            TagValue::String(value) => format!("{:?}", value),
+            TagValue::Binary(value) => format!("{:?}", String::from_utf8_lossy(value)),
+            TagValue::Double(value) => value.to_string(),
+            TagValue::Bool(value) => value.to_string(),
+            TagValue::Long(value) => value.to_string(),
+        };
+        for redaction in &self.redactions {
+            match redaction {
+                Redaction::Tag(redacted_key) if redacted_key == key => {
+                    rendered = "[redacted]".into()
+                }
+                Redaction::TagKeySegment(segment, replacement)
+                    if key.rsplit('.').next() == Some(segment.as_str()) =>
+                {
+                    rendered = replacement.clone()
+                }
+                Redaction::Tag(_) | Redaction::TagKeySegment(..) => (),
+                Redaction::Pattern(pattern, replacement) => {
+                    rendered = pattern
+                        .replace_all(&rendered, replacement.as_str())
+                        .into_owned()
+                }
+                Redaction::HexId(pattern) => {
+                    rendered = pattern
+                        .replace_all(&rendered, |captures: &Captures| {
+                            let id = &captures[0];
+                            if id.bytes().any(|b| b.is_ascii_alphabetic()) {
+                                "[id]".to_owned()
+                            } else {
+                                id.to_owned()
+                            }
+                        })
+                        .into_owned()
+                }
+            }
+        }
+        rendered
+    }
+
+    /// Compare the trace's snapshot with the one stored at `path`, or, if the
+    /// [`UPDATE_SNAPSHOTS_VARIABLE`] environment variable is set to `1` or `true`, store
+    /// it there.
+    pub fn assert_matches(
+        &self,
+        trace: &Node<SpanWithProcess>,
+        path: impl AsRef<Path>,
+    ) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        let actual = self.render(trace);
+        let result = if should_update_snapshots() {
+            write_snapshot(path, &actual)
+        } else {
+            fs::read_to_string(path)
+                .with_context(|| format!("Failed to read snapshot {}", path.display()))
+                .map(|expected| (expected != actual).then(|| diff_lines(&expected, &actual)))
+        };
+
+        match result {
+            Ok(None) => Ok(()),
+            Ok(Some(diff)) => Err(SnapshotError {
+                path: path.to_owned(),
+                reason: format!("Snapshot did not match (- expected, + actual):\n{}", diff),
+            }),
+            Err(error) => Err(SnapshotError {
+                path: path.to_owned(),
+                reason: format!("{:#}", error),
+            }),
+        }
+    }
+}
+
+fn should_update_snapshots() -> bool {
+    env::var(UPDATE_SNAPSHOTS_VARIABLE)
+        .is_ok_and(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+}
+
+fn write_snapshot(path: &Path, snapshot: &str) -> Result<Option<String>, anyhow::Error> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(path, snapshot)?;
+    Ok(None)
+}
+
+/// Describe the differences between two texts line by line, prefixing lines only in
+/// `expected` with `-` and lines only in `actual` with `+`.
+fn diff_lines(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+
+    // The length of the longest common subsequence of each pair of suffixes.
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            diff.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j < actual.len() && (i == expected.len() || common[i][j + 1] >= common[i + 1][j])
+        {
+            diff.push(format!("+ {}", actual[j]));
+            j += 1;
+        } else {
+            diff.push(format!("- {}", expected[i]));
+            i += 1;
+        }
+    }
+    diff.join("\n")
+}
+
+/// The error returned when a trace's snapshot does not match the stored one.
+#[derive(Debug)]
+pub struct SnapshotError {
+    path: PathBuf,
+    reason: String,
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}\nSet {} to update the snapshot.",
+            self.path.display(),
+            self.reason,
+            UPDATE_SNAPSHOTS_VARIABLE
+        )
+    }
+}
+
+impl std::error::Error for SnapshotError {}
@@ -0,0 +1,133 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::assertions::ExpectedTagValue;
+use crate::jaeger_models::{Process, Span, TagValue};
+
+type TagPredicate = Box<dyn Fn(&TagValue) -> bool>;
+
+/// Describes the spans to find with [`DetachedJaegerCollectorServer::find_spans`] and
+/// [`DetachedJaegerCollectorServer::find_traces`]. A span matches when it satisfies every
+/// criterion; a new query matches every span.
+///
+/// ```ignore
+/// let query = SpanQuery::new()
+///     .root_only()
+///     .operation("HTTP request")
+///     .with_tag("http.route", "/cat");
+/// let traces = collector.find_traces(&query);
+/// ```
+///
+/// [`DetachedJaegerCollectorServer::find_spans`]: crate::DetachedJaegerCollectorServer::find_spans
+/// [`DetachedJaegerCollectorServer::find_traces`]: crate::DetachedJaegerCollectorServer::find_traces
+#[derive(Default)]
+pub struct SpanQuery {
+    service: Option<String>,
+    operation: Option<String>,
+    tags: Vec<(String, TagPredicate)>,
+    root_only: bool,
+    min_duration: Option<i64>,
+    max_duration: Option<i64>,
+    started_after: Option<i64>,
+    started_before: Option<i64>,
+}
+
+impl SpanQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match spans sent by the service with the given name.
+    pub fn service(mut self, service: impl Into<String>) -> Self {
+        self.service = Some(service.into());
+        self
+    }
+
+    /// Only match spans with the given operation name.
+    pub fn operation(mut self, operation: impl Into<String>) -> Self {
+        self.operation = Some(operation.into());
+        self
+    }
+
+    /// Only match spans with a tag with the given key and value. As with Jaeger's search,
+    /// the tags of the span's process, and the fields of its logs, are also considered.
+    pub fn with_tag(self, key: impl Into<String>, value: impl Into<ExpectedTagValue>) -> Self {
+        let value = value.into();
+        self.with_tag_matching(key, move |actual| value.matches(actual))
+    }
+
+    /// Only match spans with a tag with the given key, whose value satisfies `predicate`.
+    /// See [`SpanQuery::with_tag`].
+    pub fn with_tag_matching(
+        mut self,
+        key: impl Into<String>,
+        predicate: impl Fn(&TagValue) -> bool + 'static,
+    ) -> Self {
+        self.tags.push((key.into(), Box::new(predicate)));
+        self
+    }
+
+    /// Only match the root spans of traces, which have no parent.
+    pub fn root_only(mut self) -> Self {
+        self.root_only = true;
+        self
+    }
+
+    /// Only match spans which took at least `duration`.
+    pub fn min_duration(mut self, duration: Duration) -> Self {
+        self.min_duration = Some(duration.as_micros() as i64);
+        self
+    }
+
+    /// Only match spans which took at most `duration`.
+    pub fn max_duration(mut self, duration: Duration) -> Self {
+        self.max_duration = Some(duration.as_micros() as i64);
+        self
+    }
+
+    /// Only match spans which started at or after `time`.
+    pub fn started_after(mut self, time: SystemTime) -> Self {
+        self.started_after = Some(unix_micros(time));
+        self
+    }
+
+    /// Only match spans which started at or before `time`.
+    pub fn started_before(mut self, time: SystemTime) -> Self {
+        self.started_before = Some(unix_micros(time));
+        self
+    }
+
+    /// Test whether a span, sent by the given process, matches the query.
+    pub fn matches(&self, process: &Process, span: &Span) -> bool {
+        self.service
+            .as_ref()
+            .is_none_or(|service| process.service_name == *service)
+            && self
+                .operation
+                .as_ref()
+                .is_none_or(|operation| span.operation_name == *operation)
+            && (!self.root_only || span.tree_parent_span_id().is_none())
+            && self.min_duration.is_none_or(|min| span.duration >= min)
+            && self.max_duration.is_none_or(|max| span.duration <= max)
+            && self
+                .started_after
+                .is_none_or(|start| span.start_time >= start)
+            && self.started_before.is_none_or(|end| span.start_time <= end)
+            && self.tags.iter().all(|(key, predicate)| {
+                let log_fields = span.logs.iter().flatten().flat_map(|log| &log.fields);
+                span.tags
+                    .iter()
+                    .flatten()
+                    .chain(process.tags.iter().flatten())
+                    .chain(log_fields)
+                    .filter(|tag| tag.key == *key)
+                    .any(|tag| tag.value().is_ok_and(|value| predicate(&value)))
+            })
+    }
+}
+
+fn unix_micros(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since_epoch) => since_epoch.as_micros() as i64,
+        Err(error) => -(error.duration().as_micros() as i64),
+    }
+}
@@ -0,0 +1,369 @@
+use mock_jaeger_collector::jaeger_models::{Batch, Process, Span, Tag, TagType};
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, KeyValue};
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use opentelemetry_proto::tonic::trace::v1::span::SpanKind;
+use opentelemetry_proto::tonic::trace::v1::{ResourceSpans, ScopeSpans, Span as OtlpSpan};
+use prost::Message;
+use std::collections::HashMap;
+use thrift::protocol::{
+    TBinaryOutputProtocol, TFieldIdentifier, TListIdentifier, TOutputProtocol, TStructIdentifier,
+    TType,
+};
+use thrift::OrderedFloat;
+use uuid::Uuid;
+
+/// A randomly generated trace ID, so that each test only queries for its own spans.
+pub struct TraceId {
+    pub high: i64,
+    pub low: i64,
+}
+
+impl TraceId {
+    pub fn random() -> Self {
+        let value = Uuid::new_v4().as_u128();
+        Self {
+            high: (value >> 64) as i64,
+            low: value as i64,
+        }
+    }
+
+    /// The trace ID in the form accepted by `DetachedJaegerCollectorServer::get_trace`.
+    pub fn to_hex(&self) -> String {
+        format!("{:016x}{:016x}", self.high, self.low)
+    }
+
+    /// The trace ID in the 16 byte form used by OTLP.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.high.to_be_bytes(), self.low.to_be_bytes()].concat()
+    }
+}
+
+/// Build a batch containing a root span, with a single child span, in the given trace.
+pub fn build_batch(trace_id: &TraceId) -> Batch {
+    let root_span = Span::new(
+        trace_id.low,
+        trace_id.high,
+        1,
+        0,
+        "HTTP request".into(),
+        None,
+        1,
+        1_000_000,
+        500,
+        vec![string_tag("http.method", "GET")],
+        None,
+    );
+    let child_span = Span::new(
+        trace_id.low,
+        trace_id.high,
+        2,
+        1,
+        "GET /fact".into(),
+        None,
+        1,
+        1_000_100,
+        200,
+        vec![string_tag("http.method", "GET")],
+        None,
+    );
+
+    Batch::new(
+        Process::new("test_service".into(), None),
+        vec![root_span, child_span],
+        None,
+        None,
+    )
+}
+
+pub fn string_tag(key: &str, value: &str) -> Tag {
+    Tag::new(
+        key.into(),
+        TagType::STRING,
+        value.to_owned(),
+        None,
+        None,
+        None,
+        None,
+    )
+}
+
+pub fn bool_tag(key: &str, value: bool) -> Tag {
+    Tag::new(key.into(), TagType::BOOL, None, None, value, None, None)
+}
+
+pub fn long_tag(key: &str, value: i64) -> Tag {
+    Tag::new(key.into(), TagType::LONG, None, None, None, value, None)
+}
+
+pub fn double_tag(key: &str, value: f64) -> Tag {
+    Tag::new(
+        key.into(),
+        TagType::DOUBLE,
+        None,
+        OrderedFloat(value),
+        None,
+        None,
+        None,
+    )
+}
+
+pub fn binary_tag(key: &str, value: &[u8]) -> Tag {
+    Tag::new(
+        key.into(),
+        TagType::BINARY,
+        None,
+        None,
+        None,
+        None,
+        value.to_vec(),
+    )
+}
+
+/// Build an OTLP export request with the same shape as [`build_batch`].
+pub fn build_otlp_request(trace_id: &TraceId) -> ExportTraceServiceRequest {
+    let root_span = OtlpSpan {
+        trace_id: trace_id.to_bytes(),
+        span_id: 1_i64.to_be_bytes().to_vec(),
+        name: "HTTP request".into(),
+        kind: SpanKind::Server as i32,
+        start_time_unix_nano: 1_000_000_000,
+        end_time_unix_nano: 1_000_500_000,
+        attributes: vec![string_attribute("http.method", "GET")],
+        ..Default::default()
+    };
+    let child_span = OtlpSpan {
+        trace_id: trace_id.to_bytes(),
+        span_id: 2_i64.to_be_bytes().to_vec(),
+        parent_span_id: 1_i64.to_be_bytes().to_vec(),
+        name: "GET /fact".into(),
+        kind: SpanKind::Client as i32,
+        start_time_unix_nano: 1_000_100_000,
+        end_time_unix_nano: 1_000_300_000,
+        attributes: vec![string_attribute("http.method", "GET")],
+        ..Default::default()
+    };
+
+    ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: Some(Resource {
+                attributes: vec![string_attribute("service.name", "test_service")],
+                ..Default::default()
+            }),
+            scope_spans: vec![ScopeSpans {
+                spans: vec![root_span, child_span],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    }
+}
+
+fn string_attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.into(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.into())),
+        }),
+    }
+}
+
+// The messages below mirror the parts of Zipkin's `zipkin.proto3` which
+// `build_zipkin_proto_spans` needs.
+
+#[derive(Clone, PartialEq, Message)]
+struct ZipkinListOfSpans {
+    #[prost(message, repeated, tag = "1")]
+    spans: Vec<ZipkinProtoSpan>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ZipkinProtoSpan {
+    #[prost(bytes = "vec", tag = "1")]
+    trace_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    parent_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    id: Vec<u8>,
+    #[prost(int32, tag = "4")]
+    kind: i32,
+    #[prost(string, tag = "5")]
+    name: String,
+    #[prost(fixed64, tag = "6")]
+    timestamp: u64,
+    #[prost(uint64, tag = "7")]
+    duration: u64,
+    #[prost(message, optional, tag = "8")]
+    local_endpoint: Option<ZipkinProtoEndpoint>,
+    #[prost(message, optional, tag = "9")]
+    remote_endpoint: Option<ZipkinProtoEndpoint>,
+    #[prost(map = "string, string", tag = "11")]
+    tags: HashMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ZipkinProtoEndpoint {
+    #[prost(string, tag = "1")]
+    service_name: String,
+    #[prost(int32, tag = "4")]
+    port: i32,
+}
+
+/// Encode Zipkin v2 spans with the same shape as [`build_batch`] as a protobuf
+/// `ListOfSpans`, with the child span calling `cat_facts_api`.
+pub fn build_zipkin_proto_spans(trace_id: &TraceId) -> Vec<u8> {
+    let local_endpoint = ZipkinProtoEndpoint {
+        service_name: "test_service".into(),
+        port: 0,
+    };
+    let tags: HashMap<String, String> = [("http.method".into(), "GET".into())].into();
+    let root_span = ZipkinProtoSpan {
+        trace_id: trace_id.to_bytes(),
+        id: 1_i64.to_be_bytes().to_vec(),
+        // `Kind.SERVER`
+        kind: 2,
+        name: "HTTP request".into(),
+        timestamp: 1_000_000,
+        duration: 500,
+        local_endpoint: Some(local_endpoint.clone()),
+        tags: tags.clone(),
+        ..Default::default()
+    };
+    let child_span = ZipkinProtoSpan {
+        trace_id: trace_id.to_bytes(),
+        parent_id: 1_i64.to_be_bytes().to_vec(),
+        id: 2_i64.to_be_bytes().to_vec(),
+        // `Kind.CLIENT`
+        kind: 1,
+        name: "GET /fact".into(),
+        timestamp: 1_000_100,
+        duration: 200,
+        local_endpoint: Some(local_endpoint),
+        remote_endpoint: Some(ZipkinProtoEndpoint {
+            service_name: "cat_facts_api".into(),
+            port: 443,
+        }),
+        tags,
+    };
+
+    ZipkinListOfSpans {
+        spans: vec![root_span, child_span],
+    }
+    .encode_to_vec()
+}
+
+/// Encode spans with the same shape as [`build_batch`] as a binary Thrift list in
+/// Zipkin's legacy v1 model. Their kinds and services are left to be derived from their
+/// core annotations, and the child span's server address is `cat_facts_api`.
+pub fn build_zipkin_v1_spans(trace_id: &TraceId) -> thrift::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut output = TBinaryOutputProtocol::new(&mut bytes, true);
+    output.write_list_begin(&TListIdentifier::new(TType::Struct, 2))?;
+    write_zipkin_v1_span(
+        &mut output,
+        trace_id,
+        1,
+        None,
+        "HTTP request",
+        &[(1_000_000, "sr"), (1_000_500, "ss")],
+        None,
+    )?;
+    write_zipkin_v1_span(
+        &mut output,
+        trace_id,
+        2,
+        Some(1),
+        "GET /fact",
+        &[(1_000_100, "cs"), (1_000_300, "cr")],
+        Some("cat_facts_api"),
+    )?;
+    output.write_list_end()?;
+    output.flush()?;
+    Ok(bytes)
+}
+
+fn write_zipkin_v1_span(
+    output: &mut dyn TOutputProtocol,
+    trace_id: &TraceId,
+    id: i64,
+    parent_id: Option<i64>,
+    name: &str,
+    annotations: &[(i64, &str)],
+    server_address: Option<&str>,
+) -> thrift::Result<()> {
+    output.write_struct_begin(&TStructIdentifier::new("Span"))?;
+    write_field(output, TType::I64, 1, |output| {
+        output.write_i64(trace_id.low)
+    })?;
+    write_field(output, TType::String, 3, |output| output.write_string(name))?;
+    write_field(output, TType::I64, 4, |output| output.write_i64(id))?;
+    if let Some(parent_id) = parent_id {
+        write_field(output, TType::I64, 5, |output| output.write_i64(parent_id))?;
+    }
+    write_field(output, TType::List, 6, |output| {
+        output.write_list_begin(&TListIdentifier::new(
+            TType::Struct,
+            annotations.len() as i32,
+        ))?;
+        for (timestamp, value) in annotations {
+            output.write_struct_begin(&TStructIdentifier::new("Annotation"))?;
+            write_field(output, TType::I64, 1, |output| output.write_i64(*timestamp))?;
+            write_field(output, TType::String, 2, |output| {
+                output.write_string(value)
+            })?;
+            write_field(output, TType::Struct, 3, |output| {
+                write_zipkin_v1_endpoint(output, "test_service")
+            })?;
+            output.write_field_stop()?;
+            output.write_struct_end()?;
+        }
+        output.write_list_end()
+    })?;
+    if let Some(server_address) = server_address {
+        write_field(output, TType::List, 8, |output| {
+            output.write_list_begin(&TListIdentifier::new(TType::Struct, 1))?;
+            output.write_struct_begin(&TStructIdentifier::new("BinaryAnnotation"))?;
+            write_field(output, TType::String, 1, |output| output.write_string("sa"))?;
+            write_field(output, TType::String, 2, |output| output.write_bytes(&[1]))?;
+            // `AnnotationType.BOOL`
+            write_field(output, TType::I32, 3, |output| output.write_i32(0))?;
+            write_field(output, TType::Struct, 4, |output| {
+                write_zipkin_v1_endpoint(output, server_address)
+            })?;
+            output.write_field_stop()?;
+            output.write_struct_end()?;
+            output.write_list_end()
+        })?;
+    }
+    write_field(output, TType::I64, 12, |output| {
+        output.write_i64(trace_id.high)
+    })?;
+    output.write_field_stop()?;
+    output.write_struct_end()
+}
+
+fn write_zipkin_v1_endpoint(
+    output: &mut dyn TOutputProtocol,
+    service_name: &str,
+) -> thrift::Result<()> {
+    output.write_struct_begin(&TStructIdentifier::new("Endpoint"))?;
+    write_field(output, TType::I32, 1, |output| {
+        output.write_i32(0x7f00_0001)
+    })?;
+    write_field(output, TType::String, 3, |output| {
+        output.write_string(service_name)
+    })?;
+    output.write_field_stop()?;
+    output.write_struct_end()
+}
+
+fn write_field(
+    output: &mut dyn TOutputProtocol,
+    field_type: TType,
+    id: i16,
+    write_value: impl FnOnce(&mut dyn TOutputProtocol) -> thrift::Result<()>,
+) -> thrift::Result<()> {
+    output.write_field_begin(&TFieldIdentifier::new("", field_type, id))?;
+    write_value(output)?;
+    output.write_field_end()
+}
@@ -0,0 +1,1829 @@
+use crate::test_data::{
+    binary_tag, bool_tag, build_batch, build_otlp_request, build_zipkin_proto_spans,
+    build_zipkin_v1_spans, double_tag, long_tag, string_tag, TraceId,
+};
+use actix_rt::time::sleep;
+use anyhow::anyhow;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures_util::future::join;
+use mock_jaeger_collector::assertions::{event, named, TraceAssertions};
+use mock_jaeger_collector::jaeger_models::{
+    json, Batch, Log, Span, SpanRef, SpanRefType, SpanWithProcess, TagValue,
+};
+use mock_jaeger_collector::rendering::RenderTree;
+use mock_jaeger_collector::reports::{reports_directory, TraceReport};
+use mock_jaeger_collector::snapshots::TraceSnapshot;
+use mock_jaeger_collector::{
+    Configuration, DetachedJaegerCollectorServer, Faults, SpanQuery, WaitForTraceError,
+    TEST_ID_ATTRIBUTE,
+};
+use opentelemetry_proto::tonic::collector::trace::v1::trace_service_client::TraceServiceClient;
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use prost::Message;
+use rctree::Node;
+use regex::Regex;
+use reqwest::StatusCode;
+use serde_json::json;
+use std::io::Write;
+#[cfg(feature = "cli")]
+use std::io::{BufRead, BufReader};
+use std::net::UdpSocket;
+#[cfg(feature = "cli")]
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fs};
+use thrift::protocol::{
+    TBinaryOutputProtocol, TCompactOutputProtocol, TFieldIdentifier, TMessageIdentifier,
+    TMessageType, TOutputProtocol, TStructIdentifier, TType,
+};
+use tonic::codec::CompressionEncoding;
+use uuid::Uuid;
+
+#[actix_rt::test]
+pub async fn batches_emitted_to_the_agent_endpoint_are_available_as_traces() {
+    // Arrange
+    // Start a collector, and encode a batch as a Jaeger client would for the agent
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    let packet = encode_emit_batch(&build_batch(&trace_id)).expect("Failed to encode batch");
+
+    // Act
+    // Send the batch to the agent endpoint over UDP
+    let socket = UdpSocket::bind("127.0.0.1:0").expect("Failed to bind UDP socket");
+    socket
+        .send_to(&packet, collector.agent_endpoint())
+        .expect("Failed to send packet");
+
+    // Assert
+    // The trace should be assembled from the batch's spans
+    let trace = wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    assert_eq!(trace.borrow().operation_name, "HTTP request");
+    let children: Vec<_> = trace
+        .children()
+        .map(|s| s.borrow().operation_name.clone())
+        .collect();
+    assert_eq!(children, vec!["GET /fact"]);
+}
+
+#[actix_rt::test]
+pub async fn otlp_protobuf_exports_are_available_as_traces() {
+    // Arrange
+    // Start a collector, and build an OTLP export request
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    let request = build_otlp_request(&trace_id);
+
+    // Act
+    // Post the protobuf-encoded request to the OTLP/HTTP traces endpoint
+    reqwest::Client::new()
+        .post(format!("{}/v1/traces", collector.base_url()))
+        .header("Content-Type", "application/x-protobuf")
+        .body(request.encode_to_vec())
+        .send()
+        .await
+        .expect("Failed to make request to collector")
+        .error_for_status()
+        .expect("Collector returned an error status code");
+
+    // Assert
+    // The trace should be assembled from the request's spans, with their kinds as tags
+    let trace = wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    let child = trace.first_child().expect("Root span had no children");
+    let child = child.borrow();
+    assert_eq!(child.operation_name, "GET /fact");
+    assert_eq!(child.duration, 200);
+    let span_kind = child.get_tag("span.kind").expect("No span.kind tag found");
+    assert_eq!(span_kind.value().unwrap(), TagValue::String("client"));
+}
+
+#[actix_rt::test]
+pub async fn otlp_grpc_exports_are_available_as_traces() {
+    // Arrange
+    // Start a collector, and connect an OTLP/gRPC client to it
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    let mut client = TraceServiceClient::connect(collector.grpc_endpoint())
+        .await
+        .expect("Failed to connect to collector");
+
+    // Act
+    // Export an OTLP request to the trace service
+    client
+        .export(build_otlp_request(&trace_id))
+        .await
+        .expect("Collector rejected the export request");
+
+    // Assert
+    // The trace should be assembled from the request's spans
+    let trace = wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    assert_eq!(trace.borrow().operation_name, "HTTP request");
+    assert_eq!(trace.children().count(), 1);
+}
+
+#[actix_rt::test]
+pub async fn otlp_json_exports_are_available_as_traces() {
+    // Arrange
+    // Start a collector, and build an OTLP export request in its JSON encoding
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    let body = json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [{ "key": "service.name", "value": { "stringValue": "test_service" } }]
+            },
+            "scopeSpans": [{
+                "spans": [{
+                    "traceId": trace_id.to_hex(),
+                    "spanId": "0000000000000001",
+                    "name": "HTTP request",
+                    "kind": 2,
+                    "startTimeUnixNano": "1000000000",
+                    "endTimeUnixNano": "1000500000",
+                    "attributes": [{ "key": "http.status_code", "value": { "intValue": "200" } }]
+                }]
+            }]
+        }]
+    });
+
+    // Act
+    // Post the request to the OTLP/HTTP traces endpoint
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/traces", collector.base_url()))
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to make request to collector");
+
+    // Assert
+    // The request should be accepted, and its span should be available as a trace
+    assert_eq!(response.status(), StatusCode::OK);
+    let trace = wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    let root = trace.borrow();
+    assert_eq!(root.operation_name, "HTTP request");
+    let status_code = root
+        .get_tag("http.status_code")
+        .expect("No http.status_code tag found");
+    assert_eq!(status_code.value().unwrap(), TagValue::Long(200));
+}
+
+#[actix_rt::test]
+pub async fn zipkin_json_spans_are_available_as_traces() {
+    // Arrange
+    // Start a collector, and build a list of Zipkin v2 spans in their JSON encoding
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    let local_endpoint = json!({ "serviceName": "test_service" });
+    let body = json!([
+        {
+            "traceId": trace_id.to_hex(),
+            "id": "0000000000000001",
+            "name": "HTTP request",
+            "kind": "SERVER",
+            "timestamp": 1000000,
+            "duration": 500,
+            "localEndpoint": local_endpoint
+        },
+        {
+            "traceId": trace_id.to_hex(),
+            "id": "0000000000000002",
+            "parentId": "0000000000000001",
+            "name": "GET /fact",
+            "kind": "CLIENT",
+            "timestamp": 1000100,
+            "duration": 200,
+            "localEndpoint": local_endpoint,
+            "remoteEndpoint": { "serviceName": "cat_facts_api", "port": 443 },
+            "tags": { "http.method": "GET" }
+        }
+    ]);
+
+    // Act
+    // Post the spans to the Zipkin v2 spans endpoint
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v2/spans", collector.base_url()))
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to make request to collector");
+
+    // Assert
+    // The spans should be accepted, and assembled into a trace by their hex IDs
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let trace = wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    let child = trace.first_child().expect("Root span had no children");
+    let child = child.borrow();
+    assert_eq!(child.operation_name, "GET /fact");
+    let peer_service = child
+        .get_tag("peer.service")
+        .expect("No peer.service tag found");
+    assert_eq!(
+        peer_service.value().unwrap(),
+        TagValue::String("cat_facts_api")
+    );
+}
+
+#[actix_rt::test]
+pub async fn zipkin_protobuf_spans_are_available_as_traces() {
+    // Arrange
+    // Start a collector, and encode a list of Zipkin v2 spans as protobuf
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    let body = build_zipkin_proto_spans(&trace_id);
+
+    // Act
+    // Post the spans to the Zipkin v2 spans endpoint
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v2/spans", collector.base_url()))
+        .header("Content-Type", "application/x-protobuf")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to make request to collector");
+
+    // Assert
+    // The spans should be accepted, and translated into Jaeger spans of the local service
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let trace = wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    assert_zipkin_spans_were_translated(&trace);
+    let root = trace.borrow();
+    let http_method = root
+        .get_tag("http.method")
+        .expect("No http.method tag found");
+    assert_eq!(http_method.value().unwrap(), TagValue::String("GET"));
+}
+
+#[actix_rt::test]
+pub async fn zipkin_v1_thrift_spans_are_available_as_traces() {
+    // Arrange
+    // Start a collector, and encode a list of legacy Zipkin v1 spans as binary Thrift
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    let body = build_zipkin_v1_spans(&trace_id).expect("Failed to encode spans");
+
+    // Act
+    // Post the spans to the Zipkin v1 spans endpoint
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/spans", collector.base_url()))
+        .header("Content-Type", "application/x-thrift")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to make request to collector");
+
+    // Assert
+    // The spans' kinds, services and timings should be derived from their core
+    // annotations, and their remote services from their server address annotations
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let trace = wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    assert_zipkin_spans_were_translated(&trace);
+    let child = trace.first_child().expect("Root span had no children");
+    let child = child.borrow();
+    assert_eq!(child.start_time, 1_000_100);
+    assert_eq!(child.duration, 200);
+    assert!(child.logs.is_none());
+}
+
+#[actix_rt::test]
+pub async fn malformed_zipkin_spans_are_rejected_and_recorded() {
+    // Arrange
+    // Start a collector, and encode spans in each of Zipkin's encodings
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let client = reqwest::Client::new();
+    let trace_id = TraceId::random();
+    let proto_body = build_zipkin_proto_spans(&trace_id);
+    let thrift_body = build_zipkin_v1_spans(&trace_id).expect("Failed to encode spans");
+    let json_body = json!([{
+        "traceId": trace_id.to_hex(),
+        "id": "+000000000000001",
+        "name": "HTTP request"
+    }]);
+
+    // Act
+    // Post truncated protobuf and Thrift spans, and JSON spans with an ID which is not
+    // hex, though Rust would parse it as such
+    let mut statuses = Vec::new();
+    for (path, content_type, body) in [
+        (
+            "/api/v2/spans",
+            "application/x-protobuf",
+            proto_body[..proto_body.len() - 1].to_vec(),
+        ),
+        (
+            "/api/v1/spans",
+            "application/x-thrift",
+            thrift_body[..thrift_body.len() - 1].to_vec(),
+        ),
+        (
+            "/api/v2/spans",
+            "application/json",
+            json_body.to_string().into_bytes(),
+        ),
+    ] {
+        let response = client
+            .post(format!("{}{}", collector.base_url(), path))
+            .header("Content-Type", content_type)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to make request to collector");
+        statuses.push(response.status());
+    }
+
+    // Assert
+    // Each request should be refused, and recorded, without storing any spans
+    assert_eq!(statuses, vec![StatusCode::BAD_REQUEST; 3]);
+    let rejected_batches = collector.rejected_batches();
+    let endpoints: Vec<_> = rejected_batches
+        .iter()
+        .map(|rejected| rejected.endpoint.as_str())
+        .collect();
+    assert_eq!(
+        endpoints,
+        vec!["/api/v2/spans", "/api/v1/spans", "/api/v2/spans"]
+    );
+    assert!(rejected_batches[2].error.contains("+000000000000001"));
+    assert!(collector.get_trace(&trace_id.to_hex()).await.is_err());
+}
+
+#[actix_rt::test]
+pub async fn compressed_request_bodies_are_decompressed() {
+    // Arrange
+    // Start a collector, and compress a request for each endpoint with a different encoding
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let client = reqwest::Client::new();
+    let (jaeger_trace_id, otlp_trace_id, grpc_trace_id) =
+        (TraceId::random(), TraceId::random(), TraceId::random());
+    let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+    gzip.write_all(&encode_batch(&build_batch(&jaeger_trace_id)))
+        .unwrap();
+    let otlp_request = build_otlp_request(&otlp_trace_id).encode_to_vec();
+    let mut grpc_client = TraceServiceClient::connect(collector.grpc_endpoint())
+        .await
+        .expect("Failed to connect to collector")
+        .send_compressed(CompressionEncoding::Gzip);
+
+    // Act
+    let jaeger_response = client
+        .post(format!("{}/api/traces", collector.base_url()))
+        .header("Content-Type", "application/x-thrift")
+        .header("Content-Encoding", "gzip")
+        .body(gzip.finish().unwrap())
+        .send()
+        .await
+        .expect("Failed to make request to collector");
+    let otlp_response = client
+        .post(format!("{}/v1/traces", collector.base_url()))
+        .header("Content-Type", "application/x-protobuf")
+        .header("Content-Encoding", "zstd")
+        .body(zstd::encode_all(otlp_request.as_slice(), 0).unwrap())
+        .send()
+        .await
+        .expect("Failed to make request to collector");
+    grpc_client
+        .export(build_otlp_request(&grpc_trace_id))
+        .await
+        .expect("Collector rejected the compressed export request");
+    let unsupported_response = client
+        .post(format!("{}/v1/traces", collector.base_url()))
+        .header("Content-Type", "application/x-protobuf")
+        .header("Content-Encoding", "snappy")
+        .body(otlp_request)
+        .send()
+        .await
+        .expect("Failed to make request to collector");
+
+    // Assert
+    // Each compressed request should be accepted, and its spans available as a trace,
+    // while bodies in an unknown encoding should be rejected
+    assert_eq!(jaeger_response.status(), StatusCode::ACCEPTED);
+    assert_eq!(otlp_response.status(), StatusCode::OK);
+    assert_eq!(
+        unsupported_response.status(),
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+    for trace_id in [jaeger_trace_id, otlp_trace_id, grpc_trace_id] {
+        let trace = wait_for_trace(&collector, &trace_id.to_hex())
+            .await
+            .expect("Expected trace was not available within timeout");
+        assert_eq!(trace.borrow().operation_name, "HTTP request");
+    }
+}
+
+#[actix_rt::test]
+pub async fn malformed_otlp_exports_are_rejected_as_bad_requests() {
+    // Arrange
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+
+    // Act
+    // Post a body which is not valid JSON to the OTLP/HTTP traces endpoint
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/traces", collector.base_url()))
+        .header("Content-Type", "application/json")
+        .body("{ not json")
+        .send()
+        .await
+        .expect("Failed to make request to collector");
+
+    // Assert
+    // The request should be refused, and recorded with the reason it was refused
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let rejected_batches = collector.rejected_batches();
+    assert_eq!(rejected_batches.len(), 1);
+    assert_eq!(rejected_batches[0].endpoint, "/v1/traces");
+    assert_eq!(rejected_batches[0].payload, b"{ not json");
+    assert!(rejected_batches[0]
+        .error
+        .starts_with("Failed to decode JSON export request"));
+}
+
+#[actix_rt::test]
+pub async fn otlp_exports_with_malformed_ids_are_rejected_as_invalid() {
+    // Arrange
+    // Build an export request whose child span has a truncated trace ID
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    let mut request = build_otlp_request(&trace_id);
+    request.resource_spans[0].scope_spans[0].spans[1].trace_id = vec![1, 2, 3];
+    let request_bytes = request.encode_to_vec();
+    let mut client = TraceServiceClient::connect(collector.grpc_endpoint())
+        .await
+        .expect("Failed to connect to collector");
+
+    // Act
+    // Send the request over both OTLP/HTTP and OTLP/gRPC
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/traces", collector.base_url()))
+        .header("Content-Type", "application/x-protobuf")
+        .body(request_bytes.clone())
+        .send()
+        .await
+        .expect("Failed to make request to collector");
+    let grpc_error = client
+        .export(request)
+        .await
+        .expect_err("Collector accepted the export request");
+
+    // Assert
+    // Both should be refused as invalid and recorded, and none of their spans stored
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(grpc_error.code(), tonic::Code::InvalidArgument);
+    assert_eq!(
+        grpc_error.message(),
+        "Invalid span \"GET /fact\": Trace IDs must be 16 bytes long, not 3"
+    );
+    let rejected_batches = collector.rejected_batches();
+    let summaries: Vec<_> = rejected_batches
+        .iter()
+        .map(|rejected| (rejected.endpoint.as_str(), rejected.status))
+        .collect();
+    assert_eq!(
+        summaries,
+        vec![
+            ("/v1/traces", Some(400)),
+            (
+                "/opentelemetry.proto.collector.trace.v1.TraceService/Export",
+                None
+            )
+        ]
+    );
+    for rejected in &rejected_batches {
+        assert_eq!(rejected.error, grpc_error.message());
+        assert_eq!(rejected.payload, request_bytes);
+    }
+    assert!(collector.get_trace(&trace_id.to_hex()).await.is_err());
+}
+
+#[actix_rt::test]
+pub async fn jaeger_batches_without_a_content_type_are_accepted_as_thrift() {
+    // Arrange
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+
+    // Act
+    // Post a batch without a content type, as older Jaeger clients do
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/traces", collector.base_url()))
+        .body(encode_batch(&build_batch(&trace_id)))
+        .send()
+        .await
+        .expect("Failed to make request to collector");
+
+    // Assert
+    // The batch should be accepted and decoded as binary Thrift
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let trace = wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    assert_eq!(
+        trace.render_tree().to_string(),
+        build_trace(&trace_id).render_tree().to_string()
+    );
+    assert!(collector.rejected_batches().is_empty());
+}
+
+#[actix_rt::test]
+pub async fn malformed_and_unsupported_jaeger_batches_are_rejected_and_recorded() {
+    // Arrange
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let client = reqwest::Client::new();
+    let batch = encode_batch(&build_batch(&TraceId::random()));
+
+    // Act
+    // Post a truncated batch, and a valid batch with the wrong content type, then send
+    // a packet to the agent endpoint which is not an `emitBatch` message
+    let malformed_response = client
+        .post(format!("{}/api/traces", collector.base_url()))
+        .header("Content-Type", "application/vnd.apache.thrift.binary")
+        .body(batch[..batch.len() / 2].to_vec())
+        .send()
+        .await
+        .expect("Failed to make request to collector");
+    let unsupported_response = client
+        .post(format!("{}/api/traces", collector.base_url()))
+        .header("Content-Type", "application/json")
+        .body(batch)
+        .send()
+        .await
+        .expect("Failed to make request to collector");
+    UdpSocket::bind("127.0.0.1:0")
+        .expect("Failed to bind UDP socket")
+        .send_to(b"not a batch", collector.agent_endpoint())
+        .expect("Failed to send packet");
+
+    // Assert
+    // The requests should be refused with the Jaeger collector's status codes, and each
+    // should be recorded, including the datagram, which is dropped without a response
+    assert_eq!(malformed_response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        unsupported_response.status(),
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+    let mut rejected_batches = collector.rejected_batches();
+    for _ in 0..50 {
+        if rejected_batches.len() == 3 {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+        rejected_batches = collector.rejected_batches();
+    }
+    let summaries: Vec<_> = rejected_batches
+        .iter()
+        .map(|rejected| (rejected.endpoint.as_str(), rejected.status))
+        .collect();
+    assert_eq!(
+        summaries,
+        vec![
+            ("/api/traces", Some(400)),
+            ("/api/traces", Some(415)),
+            ("agent", None)
+        ]
+    );
+    assert!(rejected_batches[0]
+        .error
+        .starts_with("Unable to process request body"));
+    assert_eq!(
+        rejected_batches[1].error,
+        "Unsupported content type: application/json"
+    );
+    assert_eq!(rejected_batches[2].payload, b"not a batch");
+}
+
+#[actix_rt::test]
+pub async fn received_traces_are_served_by_the_query_api() {
+    // Arrange
+    // Start a collector, and post a batch to it
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    post_batch(&collector, &build_batch(&trace_id)).await;
+
+    // Act
+    // Search for the service's traces, as the Jaeger UI would
+    let response: serde_json::Value = reqwest::Client::new()
+        .get(format!("{}/api/traces", collector.base_url()))
+        .query(&[
+            ("service", "test_service"),
+            ("operation", "GET /fact"),
+            ("tags", r#"{"http.method":"GET"}"#),
+            ("minDuration", "100us"),
+        ])
+        .send()
+        .await
+        .expect("Failed to make request to collector")
+        .error_for_status()
+        .expect("Collector returned an error status code")
+        .bytes()
+        .await
+        .map(|body| serde_json::from_slice(&body).expect("Response was not JSON"))
+        .expect("Failed to read response");
+
+    // Assert
+    // The trace should be returned in Jaeger's JSON model, with its parent as a reference
+    assert_eq!(response["total"], 1);
+    let trace = &response["data"][0];
+    assert_eq!(trace["traceID"], trace_id.to_hex());
+    assert_eq!(trace["processes"]["p1"]["serviceName"], "test_service");
+    let child = &trace["spans"][1];
+    assert_eq!(child["operationName"], "GET /fact");
+    assert_eq!(child["processID"], "p1");
+    assert_eq!(
+        child["references"],
+        json!([{
+            "refType": "CHILD_OF",
+            "traceID": trace_id.to_hex(),
+            "spanID": "0000000000000001"
+        }])
+    );
+}
+
+#[actix_rt::test]
+pub async fn waiting_for_a_trace_resolves_once_a_matching_batch_is_received() {
+    // Arrange
+    // Start a collector, and begin waiting for a trace with a child span
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    let trace_id_hex = trace_id.to_hex();
+    let wait = collector.wait_for_trace(
+        &trace_id_hex,
+        |trace| match trace.first_child() {
+            Some(_) => Ok(()),
+            None => Err(anyhow!("Root span had no children")),
+        },
+        Duration::from_secs(5),
+    );
+
+    // Act
+    // Post the batch while the wait is in progress
+    let post = async {
+        sleep(Duration::from_millis(100)).await;
+        post_batch(&collector, &build_batch(&trace_id)).await;
+    };
+    let (trace, _) = join(wait, post).await;
+
+    // Assert
+    let trace = trace.expect("Expected trace was not available within timeout");
+    assert_eq!(trace.borrow().operation_name, "HTTP request");
+}
+
+#[actix_rt::test]
+pub async fn waiting_for_a_trace_which_never_matches_reports_the_last_seen_trace() {
+    // Arrange
+    // Start a collector, and post a batch to it
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    post_batch(&collector, &build_batch(&trace_id)).await;
+
+    // Act
+    // Wait for the trace to contain a span it never will
+    let error = collector
+        .wait_for_trace(
+            &trace_id.to_hex(),
+            |_| Err(anyhow!("No span found named \"GET /v1/images/search\"")),
+            Duration::from_millis(200),
+        )
+        .await
+        .expect_err("Expected waiting for the trace to time out");
+
+    // Assert
+    // The error should describe the trace as it was last seen, and why it did not match
+    let last_seen = error.last_seen().expect("No trace was seen");
+    assert_eq!(last_seen.borrow().operation_name, "HTTP request");
+    let message = error.to_string();
+    assert!(message.contains("No span found named \"GET /v1/images/search\""));
+    assert!(message.contains("  GET /fact"));
+}
+
+#[actix_rt::test]
+pub async fn failing_to_wait_for_a_trace_can_be_propagated_as_an_anyhow_error() {
+    // Arrange
+    // Start a collector, and post a batch to it
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    post_batch(&collector, &build_batch(&trace_id)).await;
+    async fn wait_for_missing_span(
+        collector: &DetachedJaegerCollectorServer,
+        trace_id: &str,
+    ) -> Result<(), anyhow::Error> {
+        collector
+            .wait_for_trace(
+                trace_id,
+                |_| Err(anyhow!("No span found named \"GET /v1/images/search\"")),
+                Duration::from_millis(200),
+            )
+            .await?;
+        Ok(())
+    }
+
+    // Act
+    // Wait for the trace to contain a span it never will, propagating the error with `?`
+    let error = wait_for_missing_span(&collector, &trace_id.to_hex())
+        .await
+        .expect_err("Expected waiting for the trace to time out");
+
+    // Assert
+    // The error should still describe the trace as it was last seen
+    let error = error
+        .downcast_ref::<WaitForTraceError>()
+        .expect("Expected a WaitForTraceError");
+    let last_seen = error.last_seen().expect("No trace was seen");
+    assert_eq!(last_seen.borrow().operation_name, "HTTP request");
+    assert_eq!(
+        last_seen.render_tree().to_string(),
+        build_trace(&trace_id).render_tree().to_string()
+    );
+}
+
+#[actix_rt::test]
+pub async fn sessions_only_see_their_own_traces() {
+    // Arrange
+    // Start a collector with two sessions: one which registers its trace ID, in
+    // uppercase, and one whose batches carry its test ID
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let registering_session = collector.open_session();
+    let tagging_session = collector.open_session();
+    let registered_trace_id = TraceId::random();
+    let tagged_trace_id = TraceId::random();
+    registering_session.register_trace_id(&registered_trace_id.to_hex().to_ascii_uppercase());
+    let mut tagged_batch = build_batch(&tagged_trace_id);
+    tagged_batch.process.tags = Some(vec![string_tag(
+        TEST_ID_ATTRIBUTE,
+        tagging_session.test_id(),
+    )]);
+
+    // Act
+    post_batch(&collector, &build_batch(&registered_trace_id)).await;
+    post_batch(&collector, &tagged_batch).await;
+
+    // Assert
+    // Each session should see its own trace, but not the other's
+    let ready = |_: &Node<SpanWithProcess>| Ok(());
+    registering_session
+        .wait_for_trace(&registered_trace_id.to_hex(), ready, Duration::from_secs(5))
+        .await
+        .expect("Registered trace was not available within timeout");
+    tagging_session
+        .wait_for_trace(&tagged_trace_id.to_hex(), ready, Duration::from_secs(5))
+        .await
+        .expect("Tagged trace was not available within timeout");
+    registering_session
+        .get_trace(&registered_trace_id.to_hex().to_ascii_uppercase())
+        .await
+        .expect("Registered trace was not found by its uppercase ID");
+    assert!(registering_session
+        .get_trace(&tagged_trace_id.to_hex())
+        .await
+        .is_err());
+    assert!(tagging_session
+        .get_trace(&registered_trace_id.to_hex())
+        .await
+        .is_err());
+}
+
+#[actix_rt::test]
+pub async fn dropping_a_session_releases_its_spans() {
+    // Arrange
+    // Start a collector with a session, and send the session a trace
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let session = collector.open_session();
+    let trace_id = TraceId::random();
+    session.register_trace_id(&trace_id.to_hex());
+    post_batch(&collector, &build_batch(&trace_id)).await;
+    wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+
+    // Act
+    drop(session);
+
+    // Assert
+    assert!(collector.get_trace(&trace_id.to_hex()).await.is_err());
+}
+
+#[actix_rt::test]
+pub async fn shutting_down_the_collector_stops_its_server() {
+    // Arrange
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let base_url = collector.base_url();
+    collector.ping().await.expect("Collector was not running");
+
+    // Act
+    collector.shutdown().expect("Failed to shut down collector");
+
+    // Assert
+    // The collector should no longer accept connections
+    let result = reqwest::get(format!("{}/up", base_url)).await;
+    assert!(result.is_err());
+}
+
+#[actix_rt::test]
+pub async fn injected_faults_disrupt_ingestion_until_cleared() {
+    // Arrange
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let (failed_trace_id, dropped_trace_id, kept_trace_id, hung_trace_id) = (
+        TraceId::random(),
+        TraceId::random(),
+        TraceId::random(),
+        TraceId::random(),
+    );
+    let post = |trace_id: &TraceId| {
+        reqwest::Client::new()
+            .post(format!("{}/api/traces", collector.base_url()))
+            .header("Content-Type", "application/x-thrift")
+            .body(encode_batch(&build_batch(trace_id)))
+            .send()
+    };
+    let mut grpc_client = TraceServiceClient::connect(collector.grpc_endpoint())
+        .await
+        .expect("Failed to connect to collector");
+
+    // Act
+    // Fail requests with a 503, then drop every second batch, then hang until the faults
+    // are cleared
+    collector
+        .set_faults(Faults {
+            status: Some(StatusCode::SERVICE_UNAVAILABLE),
+            latency: Some(Duration::from_millis(50)),
+            ..Faults::default()
+        })
+        .expect("Failed to set faults");
+    let started = Instant::now();
+    let failed_response = post(&failed_trace_id)
+        .await
+        .expect("Failed to make request to collector");
+    let failed_latency = started.elapsed();
+    let grpc_error = grpc_client
+        .export(build_otlp_request(&failed_trace_id))
+        .await
+        .expect_err("Expected the export to fail");
+    let query_response = reqwest::get(format!("{}/api/services", collector.base_url()))
+        .await
+        .expect("Failed to make request to collector");
+
+    collector
+        .set_faults(Faults {
+            drop_every_nth: Some(2),
+            ..Faults::default()
+        })
+        .expect("Failed to set faults");
+    let kept_response = post(&kept_trace_id)
+        .await
+        .expect("Failed to make request to collector");
+    let dropped_response = post(&dropped_trace_id)
+        .await
+        .expect("Failed to make request to collector");
+
+    collector
+        .set_faults(Faults {
+            hang: true,
+            ..Faults::default()
+        })
+        .expect("Failed to set faults");
+    let started = Instant::now();
+    let release = async {
+        sleep(Duration::from_millis(100)).await;
+        collector.clear_faults();
+    };
+    let (hung_response, _) = join(post(&hung_trace_id), release).await;
+    let hung_latency = started.elapsed();
+
+    // Assert
+    // Failed and dropped batches should not be stored, but the query API should be
+    // unaffected, and hanging requests should complete once the faults are cleared
+    assert_eq!(failed_response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(failed_latency >= Duration::from_millis(50));
+    assert_eq!(grpc_error.code(), tonic::Code::Unavailable);
+    assert_eq!(query_response.status(), StatusCode::OK);
+    assert_eq!(kept_response.status(), StatusCode::ACCEPTED);
+    assert_eq!(dropped_response.status(), StatusCode::ACCEPTED);
+    assert_eq!(
+        hung_response
+            .expect("Failed to make request to collector")
+            .status(),
+        StatusCode::ACCEPTED
+    );
+    assert!(hung_latency >= Duration::from_millis(100));
+    assert_eq!(collector.faults(), Faults::default());
+
+    wait_for_trace(&collector, &kept_trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    wait_for_trace(&collector, &hung_trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    assert!(collector
+        .get_trace_forest(&failed_trace_id.to_hex())
+        .roots()
+        .is_empty());
+    assert!(collector
+        .get_trace_forest(&dropped_trace_id.to_hex())
+        .roots()
+        .is_empty());
+}
+
+#[test]
+pub fn faults_cannot_refuse_requests_with_statuses_which_are_not_errors() {
+    // Arrange
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+
+    // Act
+    let result = collector.set_faults(Faults {
+        status: Some(StatusCode::OK),
+        ..Faults::default()
+    });
+
+    // Assert
+    let error = result.expect_err("Expected the faults to be refused");
+    assert!(error.to_string().contains("not 200 OK"));
+    assert_eq!(collector.faults(), Faults::default());
+}
+
+#[actix_rt::test]
+pub async fn dropping_every_nth_batch_counts_each_batch_in_a_request() {
+    // Arrange
+    // Build an OTLP export request carrying a batch from each of two services
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let (kept_trace_id, dropped_trace_id) = (TraceId::random(), TraceId::random());
+    let mut request = build_otlp_request(&kept_trace_id);
+    request
+        .resource_spans
+        .extend(build_otlp_request(&dropped_trace_id).resource_spans);
+    let mut client = TraceServiceClient::connect(collector.grpc_endpoint())
+        .await
+        .expect("Failed to connect to collector");
+    collector
+        .set_faults(Faults {
+            drop_every_nth: Some(2),
+            ..Faults::default()
+        })
+        .expect("Failed to set faults");
+
+    // Act
+    // Send an empty export, then the request with two batches
+    client
+        .export(ExportTraceServiceRequest::default())
+        .await
+        .expect("Failed to export empty request");
+    client
+        .export(request)
+        .await
+        .expect("Failed to export request");
+
+    // Assert
+    // Only the second batch should be dropped, as the empty export carried none
+    wait_for_trace(&collector, &kept_trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    assert!(collector
+        .get_trace(&dropped_trace_id.to_hex())
+        .await
+        .is_err());
+}
+
+#[actix_rt::test]
+#[cfg(feature = "cli")]
+pub async fn the_standalone_collector_listens_and_persists_batches_as_configured() {
+    // Arrange
+    // Run the binary on ports allocated by the operating system, persisting to a file
+    let persist_path = env::temp_dir().join(format!("batches-{}.bin", Uuid::new_v4()));
+    let mut collector = StandaloneCollector::run(&[
+        "--http-port",
+        "0",
+        "--udp-port",
+        "0",
+        "--otlp-port",
+        "0",
+        "--persist-file",
+        persist_path.to_str().unwrap(),
+    ]);
+    let listening = collector.read_line();
+    let base_url = Regex::new(r"over HTTP at (http://127\.0\.0\.1:\d+),")
+        .unwrap()
+        .captures(&listening)
+        .unwrap_or_else(|| panic!("Unexpected output: {}", listening))[1]
+        .to_owned();
+    let trace_id = TraceId::random();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/traces", base_url))
+        .header("Content-Type", "application/x-thrift")
+        .body(encode_batch(&build_batch(&trace_id)))
+        .send()
+        .await
+        .expect("Failed to make request to collector");
+
+    // Assert
+    // The batch should be summarised, and persisted to the file
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert_eq!(
+        collector.read_line(),
+        format!(
+            "Batch 1: 2 spans from test_service, in traces {}",
+            trace_id.to_hex()
+        )
+    );
+    drop(collector);
+    let loaded_collector =
+        DetachedJaegerCollectorServer::load(&persist_path).expect("Failed to load collector");
+    fs::remove_file(&persist_path).expect("Failed to remove persisted batches");
+    assert!(loaded_collector.get_trace(&trace_id.to_hex()).await.is_ok());
+}
+
+#[test]
+#[cfg(feature = "cli")]
+pub fn the_standalone_collector_refuses_invalid_arguments() {
+    // Act
+    let output = Command::new(env!("CARGO_BIN_EXE_mock_jaeger_collector"))
+        .args(["--http-port", "not-a-port"])
+        .output()
+        .expect("Failed to run collector");
+
+    // Assert
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--http-port"));
+}
+
+#[actix_rt::test]
+pub async fn persisted_batches_can_be_loaded_into_a_new_collector() {
+    // Arrange
+    // Start a collector which persists the batches it receives, and send it a trace
+    let persist_path = env::temp_dir().join(format!("batches-{}.bin", Uuid::new_v4()));
+    let collector = DetachedJaegerCollectorServer::start_with_configuration(Configuration {
+        persist_path: Some(persist_path.clone()),
+        ..Configuration::default()
+    })
+    .expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    post_batch(&collector, &build_batch(&trace_id)).await;
+    wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    collector.shutdown().expect("Failed to shut down collector");
+
+    // Act
+    let loaded_collector =
+        DetachedJaegerCollectorServer::load(&persist_path).expect("Failed to load collector");
+    fs::remove_file(&persist_path).expect("Failed to remove persisted batches");
+
+    // Assert
+    // The trace should be available from the loaded collector
+    let trace = loaded_collector
+        .get_trace(&trace_id.to_hex())
+        .await
+        .expect("Trace was not loaded");
+    assert_eq!(trace.borrow().operation_name, "HTTP request");
+    assert_eq!(trace.children().count(), 1);
+}
+
+#[actix_rt::test]
+#[cfg(target_os = "linux")]
+pub async fn batches_which_cannot_be_persisted_are_reported() {
+    // Arrange
+    // Start a collector which persists to a device which is always full
+    let collector = DetachedJaegerCollectorServer::start_with_configuration(Configuration {
+        persist_path: Some("/dev/full".into()),
+        ..Configuration::default()
+    })
+    .expect("Failed to start collector");
+    let trace_id = TraceId::random();
+
+    // Act
+    post_batch(&collector, &build_batch(&trace_id)).await;
+
+    // Assert
+    // The failure should be reported, while the trace is still stored in memory
+    wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    let persistence_errors = collector.persistence_errors();
+    assert_eq!(persistence_errors.len(), 1);
+    assert!(persistence_errors[0].starts_with("Failed to persist batch: "));
+}
+
+#[test]
+pub fn persisted_batches_with_implausible_lengths_are_not_loaded() {
+    // Arrange
+    // Write a file whose first batch claims to be 4GiB long
+    let persist_path = env::temp_dir().join(format!("batches-{}.bin", Uuid::new_v4()));
+    fs::write(&persist_path, u32::MAX.to_be_bytes()).expect("Failed to write batches");
+
+    // Act
+    let result = DetachedJaegerCollectorServer::load(&persist_path);
+    fs::remove_file(&persist_path).expect("Failed to remove persisted batches");
+
+    // Assert
+    let error = result.err().expect("Expected loading the batches to fail");
+    assert!(format!("{:#}", error).contains("Batch 1 is said to be 4294967295 bytes long"));
+}
+
+#[actix_rt::test]
+pub async fn spans_with_only_references_to_their_parents_are_assembled_into_the_trace() {
+    // Arrange
+    // Add a span which follows from the child span, and a span whose parent is only given
+    // as a `CHILD_OF` reference
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    let mut batch = build_batch(&trace_id);
+    let span_with_references = |span_id, operation_name: &str, ref_type, parent_span_id| {
+        let reference = SpanRef::new(ref_type, trace_id.low, trace_id.high, parent_span_id);
+        Span::new(
+            trace_id.low,
+            trace_id.high,
+            span_id,
+            0,
+            operation_name.into(),
+            vec![reference],
+            1,
+            1_000_400,
+            100,
+            None,
+            None,
+        )
+    };
+    batch.spans.push(span_with_references(
+        3,
+        "process fact",
+        SpanRefType::FOLLOWS_FROM,
+        2,
+    ));
+    batch.spans.push(span_with_references(
+        4,
+        "render response",
+        SpanRefType::CHILD_OF,
+        1,
+    ));
+
+    // Act
+    post_batch(&collector, &batch).await;
+
+    // Assert
+    // The tree should place each span beneath the span it refers to
+    let trace = wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    trace
+        .expect_span(named("process fact").child_of(named("GET /fact")))
+        .expect("Span following from its parent was not in the tree");
+    trace
+        .expect_span(named("render response").child_of(named("HTTP request")))
+        .expect("Span with a CHILD_OF reference was not in the tree");
+
+    // The graph should distinguish the types of reference
+    let graph = collector.get_trace_graph(&trace_id.to_hex());
+    let operation_names = |spans: Vec<&SpanWithProcess>| -> Vec<String> {
+        spans
+            .into_iter()
+            .map(|span| span.operation_name.clone())
+            .collect()
+    };
+    assert_eq!(
+        operation_names(graph.referenced_by(2, SpanRefType::FOLLOWS_FROM)),
+        vec!["process fact"]
+    );
+    assert_eq!(
+        operation_names(graph.referenced_by(1, SpanRefType::CHILD_OF)),
+        vec!["GET /fact", "render response"]
+    );
+    assert!(graph.referenced_by(2, SpanRefType::CHILD_OF).is_empty());
+}
+
+#[actix_rt::test]
+pub async fn traces_missing_their_root_span_can_be_assembled_as_a_forest() {
+    // Arrange
+    // Drop the root span, as though it were sent to another collector
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    let mut batch = build_batch(&trace_id);
+    batch.spans.remove(0);
+
+    // Act
+    post_batch(&collector, &batch).await;
+
+    // Assert
+    // The trace cannot be assembled into a single tree...
+    let error = collector
+        .get_trace(&trace_id.to_hex())
+        .await
+        .expect_err("Expected trace to be incomplete");
+    assert_eq!(
+        error.to_string(),
+        "Spans found with missing parents: 0000000000000001"
+    );
+
+    // ...but its spans can still be asserted on
+    let forest = collector.get_trace_forest(&trace_id.to_hex());
+    assert!(!forest.is_complete());
+    assert_eq!(forest.missing_parent_span_ids(), [1]);
+    assert_eq!(forest.orphans().count(), 1);
+    forest
+        .expect_span(named("GET /fact").with_tag("http.method", "GET"))
+        .expect("Expected span was not found");
+}
+
+#[actix_rt::test]
+pub async fn spans_and_traces_can_be_found_by_query() {
+    // Arrange
+    // Send two traces, one of which has a failed span
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let failed_trace_id = TraceId::random();
+    let mut failed_batch = build_batch(&failed_trace_id);
+    failed_batch.spans[1]
+        .tags
+        .get_or_insert_with(Vec::new)
+        .push(bool_tag("error", true));
+    post_batch(&collector, &failed_batch).await;
+    post_batch(&collector, &build_batch(&TraceId::random())).await;
+
+    // Act
+    let failed_spans = collector.find_spans(&SpanQuery::new().with_tag("error", true));
+    let root_spans = collector.find_spans(
+        &SpanQuery::new()
+            .service("test_service")
+            .root_only()
+            .with_tag("http.method", "GET"),
+    );
+    let failed_traces = collector.find_traces(
+        &SpanQuery::new()
+            .operation("GET /fact")
+            .with_tag("error", true)
+            .max_duration(Duration::from_millis(1)),
+    );
+    let future_spans = collector.find_spans(&SpanQuery::new().started_after(SystemTime::now()));
+
+    // Assert
+    assert_eq!(failed_spans.len(), 1);
+    assert_eq!(failed_spans[0].operation_name, "GET /fact");
+    assert_eq!(root_spans.len(), 2);
+    assert!(root_spans
+        .iter()
+        .all(|span| span.operation_name == "HTTP request"));
+    assert_eq!(failed_traces.len(), 1);
+    let failed_trace = failed_traces[0]
+        .clone()
+        .into_tree()
+        .expect("Failed to assemble trace");
+    assert_eq!(
+        failed_trace.borrow().hex_trace_id(),
+        failed_trace_id.to_hex()
+    );
+    assert!(future_spans.is_empty());
+}
+
+#[actix_rt::test]
+pub async fn spans_carry_the_process_which_sent_them() {
+    // Arrange
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    let mut batch = build_batch(&trace_id);
+    batch.process.tags = Some(vec![string_tag("hostname", "test-host")]);
+
+    // Act
+    post_batch(&collector, &batch).await;
+
+    // Assert
+    let trace = wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+    let span = trace
+        .expect_span(
+            named("GET /fact")
+                .from_service("test_service")
+                .with_process_tag("hostname", "test-host"),
+        )
+        .expect("Expected span was not found");
+    assert_eq!(span.borrow().service_name(), "test_service");
+    let error = trace
+        .expect_span(named("GET /fact").from_service("cat_server"))
+        .expect_err("Expected no span to match");
+    assert!(error
+        .to_string()
+        .contains("GET /fact [test_service] (+100µs, 200µs, UNSET) http.method=GET  <- service was \"test_service\""));
+}
+
+#[test]
+pub fn span_matchers_find_spans_by_name_tags_and_parent() {
+    // Arrange
+    let trace = build_trace(&TraceId::random());
+
+    // Act
+    let span = trace.expect_span(
+        named("GET /fact")
+            .with_tag("http.method", "GET")
+            .child_of(named("HTTP request")),
+    );
+
+    // Assert
+    let span = span.expect("Expected span was not found");
+    assert_eq!(span.borrow().span_id, 2);
+}
+
+#[test]
+pub fn span_matchers_describe_why_spans_did_not_match() {
+    // Arrange
+    let trace = build_trace(&TraceId::random());
+
+    // Act
+    let error = trace
+        .expect_span(named("GET /fact").with_tag("http.method", "POST"))
+        .expect_err("Expected no span to match");
+
+    // Assert
+    // The error should show the actual tree, with the reason the candidate span didn't match
+    assert_eq!(
+        error.to_string(),
+        "No span matched: span named \"GET /fact\" with http.method = \"POST\"\n\
+         Actual trace:\n\
+         HTTP request [test_service] (+0ns, 500µs, UNSET) http.method=GET\n  \
+         GET /fact [test_service] (+100µs, 200µs, UNSET) http.method=GET  \
+         <- http.method was String(\"GET\"), not \"POST\""
+    );
+}
+
+#[test]
+pub fn span_events_can_be_looked_up_and_matched() {
+    // Arrange
+    // Record an exception event on the child span
+    let trace = build_trace(&TraceId::random());
+    trace.first_child().unwrap().borrow_mut().logs = Some(vec![Log::new(
+        1_000_150,
+        vec![
+            string_tag("event", "exception"),
+            string_tag("exception.message", "Request timed out"),
+        ],
+    )]);
+
+    // Act
+    let found = trace.expect_span(
+        named("GET /fact")
+            .with_event(event("exception").with_field("exception.message", "Request timed out")),
+    );
+    let error = trace
+        .expect_span(
+            named("GET /fact")
+                .with_event(event("exception").with_field("exception.message", "Not found")),
+        )
+        .expect_err("Expected no span to match");
+
+    // Assert
+    let span = found.expect("Expected span was not found");
+    let exception = span
+        .borrow()
+        .find_event("exception")
+        .cloned()
+        .expect("Exception event was not found");
+    assert_eq!(
+        exception.time(),
+        UNIX_EPOCH + Duration::from_micros(1_000_150)
+    );
+    assert_eq!(
+        exception
+            .get_field("exception.message")
+            .unwrap()
+            .value()
+            .unwrap(),
+        TagValue::String("Request timed out")
+    );
+    assert!(error.to_string().contains(
+        "<- event \"exception\" exception.message was String(\"Request timed out\"), \
+         not \"Not found\""
+    ));
+}
+
+#[test]
+pub fn span_tags_can_be_read_and_matched_as_rust_values() {
+    // Arrange
+    // Tag the child span as a client sending the status code as a double, as some
+    // exporters do, with a JSON request body
+    let trace = build_trace(&TraceId::random());
+    trace.first_child().unwrap().borrow_mut().tags = Some(vec![
+        string_tag("http.method", "GET"),
+        double_tag("http.status_code", 200.0),
+        long_tag("http.response_content_length", 42),
+        bool_tag("error", false),
+        binary_tag("http.request.body", br#"{"breed": "tabby"}"#),
+    ]);
+
+    // Act
+    let strict = trace.expect_span(named("GET /fact").with_tag("http.status_code", 200_u16));
+    let loose = trace.expect_span(
+        named("GET /fact")
+            .with_tag("http.status_code", 200_u64)
+            .with_loose_numbers(),
+    );
+    let sized = trace.expect_span(
+        named("GET /fact")
+            .with_tag("http.response_content_length", 42_usize)
+            .with_tag("http.response_content_length", 42_isize),
+    );
+    let oversized =
+        trace.expect_span(named("GET /fact").with_tag("http.response_content_length", u64::MAX));
+
+    // Assert
+    let span = trace.first_child().unwrap();
+    let span = span.borrow();
+    assert_eq!(span.tag_str("http.method"), Some("GET"));
+    assert_eq!(span.tag_f64("http.status_code"), Some(200.0));
+    assert_eq!(span.tag_i64("http.status_code"), None);
+    assert_eq!(span.tag_i64("http.response_content_length"), Some(42));
+    assert_eq!(span.tag_bool("error"), Some(false));
+    let body = span.tag_value("http.request.body").unwrap();
+    assert_eq!(body.decode_utf8().unwrap(), r#"{"breed": "tabby"}"#);
+    assert_eq!(
+        body.decode_json::<serde_json::Value>().unwrap(),
+        json!({ "breed": "tabby" })
+    );
+
+    assert!(strict
+        .expect_err("Expected no span to match")
+        .to_string()
+        .contains(
+            "<- http.status_code was Double(200.0), not Long(200); to compare numbers of either \
+         type by value, use with_loose_numbers"
+        ));
+    loose.expect("Expected span was not found");
+    sized.expect("Expected span was not found");
+    assert!(oversized
+        .expect_err("Expected no span to match")
+        .to_string()
+        .contains("was Long(42), not 9223372036854775807"));
+}
+
+#[test]
+pub fn traces_round_trip_through_jaeger_json() {
+    // Arrange
+    let trace_id = TraceId::random();
+    let batch = build_batch(&trace_id);
+    let trace = build_trace(&trace_id);
+
+    // Act
+    // Write the trace as the Jaeger UI would download it, then read it back
+    let trace_json = serde_json::to_value(json::Trace::from_tree(&trace)).unwrap();
+    let downloaded = json!({ "data": [trace_json.clone()] }).to_string();
+    let traces = json::parse_traces(&downloaded).expect("Failed to parse traces");
+    let batches = traces[0].to_batches().expect("Failed to convert trace");
+
+    // Assert
+    // The JSON should have the shape of Jaeger's `model/json`
+    assert_eq!(trace_json["traceID"], json!(trace_id.to_hex()));
+    assert_eq!(
+        trace_json["processes"]["p1"],
+        json!({ "serviceName": "test_service", "tags": [] })
+    );
+    assert_eq!(
+        trace_json["spans"][1]["references"],
+        json!([{ "refType": "CHILD_OF", "traceID": trace_id.to_hex(), "spanID": "0000000000000001" }])
+    );
+    assert_eq!(
+        trace_json["spans"][1]["tags"],
+        json!([{ "key": "http.method", "type": "string", "value": "GET" }])
+    );
+    // And converting it back should give the batch the trace was built from
+    assert_eq!(batches, vec![batch]);
+}
+
+#[test]
+pub fn jaeger_json_with_ids_which_are_not_hex_is_refused() {
+    // Arrange
+    // Write a trace as JSON, then sign the trace ID, and then the span ID, of its root span
+    let trace_json =
+        serde_json::to_value(json::Trace::from_tree(&build_trace(&TraceId::random()))).unwrap();
+    let mut signed_trace_id = trace_json.clone();
+    signed_trace_id["spans"][0]["traceID"] = json!("+0000000000000000000000000000001");
+    let mut signed_span_id = trace_json;
+    signed_span_id["spans"][0]["spanID"] = json!("+000000000000001");
+
+    // Act
+    let results: Vec<_> = [signed_trace_id, signed_span_id]
+        .iter()
+        .map(|trace_json| {
+            let downloaded = json!({ "data": [trace_json] }).to_string();
+            json::parse_traces(&downloaded).and_then(|traces| traces[0].to_batches())
+        })
+        .collect();
+
+    // Assert
+    let errors: Vec<String> = results
+        .into_iter()
+        .map(|result| format!("{:#}", result.expect_err("Expected the IDs to be refused")))
+        .collect();
+    assert!(errors[0].contains("Invalid trace ID: \"+0000000000000000000000000000001\""));
+    assert!(errors[1].contains("Invalid span ID: \"+000000000000001\""));
+}
+
+#[test]
+pub fn traces_render_as_a_waterfall_tree() {
+    // Arrange
+    let trace = build_trace(&TraceId::random());
+    trace
+        .first_child()
+        .unwrap()
+        .borrow_mut()
+        .tags
+        .get_or_insert_with(Vec::new)
+        .push(bool_tag("error", true));
+
+    // Act
+    let rendered = trace.render_tree().to_string();
+
+    // Assert
+    assert_eq!(
+        rendered,
+        "HTTP request [test_service] (+0ns, 500µs, UNSET) http.method=GET\n  \
+         GET /fact [test_service] (+100µs, 200µs, ERROR) http.method=GET"
+    );
+}
+
+#[actix_rt::test]
+pub async fn traces_can_be_exported_as_diagrams_of_the_services_involved() {
+    // Arrange
+    // Send a trace to a collector, so that it knows which service sent each span
+    let collector = DetachedJaegerCollectorServer::start().expect("Failed to start collector");
+    let trace_id = TraceId::random();
+    post_batch(&collector, &build_batch(&trace_id)).await;
+    let trace = wait_for_trace(&collector, &trace_id.to_hex())
+        .await
+        .expect("Expected trace was not available within timeout");
+
+    // Act
+    let report = TraceReport::new(&trace);
+    let paths = report
+        .write(&trace_id.to_hex())
+        .expect("Failed to write reports");
+
+    // Assert
+    assert_eq!(
+        report.to_mermaid(),
+        "sequenceDiagram\n    \
+         participant s0 as test_service\n    \
+         s0->>+s0: HTTP request\n    \
+         s0->>+s0: GET /fact\n    \
+         s0-->>-s0: 200µs\n    \
+         s0-->>-s0: 500µs\n"
+    );
+    assert!(report
+        .to_dot()
+        .contains("\"0000000000000001\" -> \"0000000000000002\";"));
+    assert!(report
+        .to_html()
+        .contains("GET /fact <span class=\"service\">test_service</span>"));
+    assert_eq!(paths.len(), 3);
+    for path in paths {
+        assert!(path.starts_with(reports_directory()));
+        fs::remove_file(&path).expect("Report was not written");
+    }
+}
+
+#[test]
+pub fn trace_snapshots_match_the_stored_snapshot() {
+    // Arrange
+    // Build a trace with a random ID, which the snapshot should not depend on
+    let trace_id = TraceId::random();
+    let mut trace = build_trace(&trace_id);
+    let trace_id_hex = trace_id.to_hex();
+    trace
+        .borrow_mut()
+        .tags
+        .get_or_insert_with(Vec::new)
+        .push(string_tag("http.url", "http://127.0.0.1:58213/fact"));
+    trace
+        .first_child()
+        .unwrap()
+        .borrow_mut()
+        .tags
+        .get_or_insert_with(Vec::new)
+        .push(string_tag("request.trace_id", &trace_id_hex));
+    let snapshot =
+        TraceSnapshot::new().with_tag_values(["http.method", "http.url", "request.trace_id"]);
+
+    // Act
+    let result = snapshot.assert_matches(
+        &trace,
+        concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/all/snapshots/trace.snap"
+        ),
+    );
+
+    // Assert
+    result.expect("Trace did not match the snapshot");
+}
+
+#[test]
+pub fn trace_snapshots_redact_the_values_of_port_tags() {
+    // Arrange
+    // Give the root span tags whose keys end in `port`, only some of which are ports
+    let mut trace = build_trace(&TraceId::random());
+    trace.borrow_mut().tags = Some(vec![
+        long_tag("net.peer.port", 58213),
+        long_tag("port", 8080),
+        string_tag("net.transport", "ip_tcp"),
+    ]);
+    let snapshot = TraceSnapshot::new().with_tag_values(["net.peer.port", "port", "net.transport"]);
+
+    // Act
+    let rendered = snapshot.render(&trace);
+
+    // Assert
+    assert_eq!(
+        rendered.lines().next(),
+        Some("HTTP request {net.peer.port=[port], net.transport=\"ip_tcp\", port=[port]}")
+    );
+}
+
+#[test]
+pub fn trace_snapshots_only_redact_values_shaped_like_ids_and_addresses() {
+    // Arrange
+    // Give the root span tags which look a little like IDs and ports, but are not, and
+    // some which are
+    let mut trace = build_trace(&TraceId::random());
+    trace.borrow_mut().tags = Some(vec![
+        string_tag("a.time", "12:30"),
+        string_tag("b.status", "key:200"),
+        string_tag("c.account", "1234567890123456"),
+        string_tag("d.span_id", "00000000000000ab"),
+        string_tag("e.url", "http://localhost:3000/fact"),
+        string_tag("f.address", "[::1]:8080"),
+        string_tag("g.address", "10.0.0.1:443"),
+    ]);
+    let snapshot = TraceSnapshot::new().with_tag_values([
+        "a.time",
+        "b.status",
+        "c.account",
+        "d.span_id",
+        "e.url",
+        "f.address",
+        "g.address",
+    ]);
+
+    // Act
+    let rendered = snapshot.render(&trace);
+
+    // Assert
+    assert_eq!(
+        rendered.lines().next(),
+        Some(
+            "HTTP request {a.time=\"12:30\", b.status=\"key:200\", \
+             c.account=\"1234567890123456\", d.span_id=\"[id]\", \
+             e.url=\"http://localhost:[port]/fact\", f.address=\"[::1]:[port]\", \
+             g.address=\"10.0.0.1:[port]\"}"
+        )
+    );
+}
+
+#[test]
+pub fn trace_snapshots_apply_custom_redactions() {
+    // Arrange
+    let trace = build_trace(&TraceId::random());
+    let snapshot = TraceSnapshot::new()
+        .with_tag_values(["http.method"])
+        .redact_pattern(Regex::new("GET|POST").unwrap(), "[method]");
+
+    // Act
+    let rendered = snapshot.render(&trace);
+
+    // Assert
+    assert_eq!(
+        rendered,
+        "HTTP request {http.method=\"[method]\"}\n  GET /fact {http.method=\"[method]\"}\n"
+    );
+}
+
+/// Check that the spans of [`build_zipkin_proto_spans`] or [`build_zipkin_v1_spans`] were
+/// translated into a Jaeger trace of the same shape, with their kinds and services.
+fn assert_zipkin_spans_were_translated(trace: &Node<SpanWithProcess>) {
+    let root = trace.borrow();
+    assert_eq!(root.operation_name, "HTTP request");
+    assert_eq!(root.service_name(), "test_service");
+    let span_kind = root.get_tag("span.kind").expect("No span.kind tag found");
+    assert_eq!(span_kind.value().unwrap(), TagValue::String("server"));
+
+    let child = trace.first_child().expect("Root span had no children");
+    let child = child.borrow();
+    assert_eq!(child.operation_name, "GET /fact");
+    assert_eq!(child.service_name(), "test_service");
+    let span_kind = child.get_tag("span.kind").expect("No span.kind tag found");
+    assert_eq!(span_kind.value().unwrap(), TagValue::String("client"));
+    let peer_service = child
+        .get_tag("peer.service")
+        .expect("No peer.service tag found");
+    assert_eq!(
+        peer_service.value().unwrap(),
+        TagValue::String("cat_facts_api")
+    );
+}
+
+/// The standalone collector binary, running until this is dropped.
+#[cfg(feature = "cli")]
+struct StandaloneCollector {
+    process: Child,
+    stdout: BufReader<ChildStdout>,
+}
+
+#[cfg(feature = "cli")]
+impl StandaloneCollector {
+    fn run(arguments: &[&str]) -> Self {
+        let mut process = Command::new(env!("CARGO_BIN_EXE_mock_jaeger_collector"))
+            .args(arguments)
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to run collector");
+        let stdout = BufReader::new(process.stdout.take().unwrap());
+        Self { process, stdout }
+    }
+
+    /// Read the next line the collector prints, without its line ending.
+    fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        self.stdout
+            .read_line(&mut line)
+            .expect("Failed to read collector output");
+        line.trim_end().to_owned()
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Drop for StandaloneCollector {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+fn encode_emit_batch(batch: &Batch) -> Result<Vec<u8>, anyhow::Error> {
+    let mut bytes = Vec::new();
+    let mut compact_output = TCompactOutputProtocol::new(&mut bytes);
+    compact_output.write_message_begin(&TMessageIdentifier::new(
+        "emitBatch",
+        TMessageType::OneWay,
+        1,
+    ))?;
+    compact_output.write_struct_begin(&TStructIdentifier::new("emitBatch_args"))?;
+    compact_output.write_field_begin(&TFieldIdentifier::new("batch", TType::Struct, 1))?;
+    batch.write_to_out_protocol(&mut compact_output)?;
+    compact_output.write_field_end()?;
+    compact_output.write_field_stop()?;
+    compact_output.write_struct_end()?;
+    compact_output.write_message_end()?;
+    compact_output.flush()?;
+    drop(compact_output);
+    Ok(bytes)
+}
+
+fn encode_batch(batch: &Batch) -> Vec<u8> {
+    let mut batch_bytes = Vec::new();
+    batch
+        .write_to_out_protocol(&mut TBinaryOutputProtocol::new(&mut batch_bytes, true))
+        .expect("Failed to encode batch");
+    batch_bytes
+}
+
+/// Build the trace contained in [`build_batch`] directly, without sending it to a collector.
+fn build_trace(trace_id: &TraceId) -> Node<SpanWithProcess> {
+    let batch = build_batch(trace_id);
+    let process = Arc::new(batch.process);
+    let mut spans = batch
+        .spans
+        .into_iter()
+        .map(|span| SpanWithProcess::new(span, process.clone()));
+    let mut root = Node::new(spans.next().unwrap());
+    root.append(Node::new(spans.next().unwrap()));
+    root
+}
+
+async fn post_batch(collector: &DetachedJaegerCollectorServer, batch: &Batch) {
+    reqwest::Client::new()
+        .post(format!("{}/api/traces", collector.base_url()))
+        .header("Content-Type", "application/x-thrift")
+        .body(encode_batch(batch))
+        .send()
+        .await
+        .expect("Failed to make request to collector")
+        .error_for_status()
+        .expect("Collector returned an error status code");
+}
+
+async fn wait_for_trace(
+    collector: &DetachedJaegerCollectorServer,
+    trace_id: &str,
+) -> Result<Node<SpanWithProcess>, WaitForTraceError> {
+    collector
+        .wait_for_trace(trace_id, |_| Ok(()), Duration::from_secs(5))
+        .await
+}